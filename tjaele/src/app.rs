@@ -1,21 +1,32 @@
+mod curve_editor;
+mod trends;
+
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use crossterm::event::KeyCode;
-use http_body_util::{BodyExt, Empty};
-use hyper::{
-    body::{Buf, Bytes},
-    Request,
-};
-use hyper_util::rt::TokioIo;
+use crossterm::event::{KeyCode, KeyModifiers};
 use ratatui::crossterm::{self, event::KeyEvent};
-use tjaele_types::{GpuState, SOCKET};
-use tokio::net::UnixStream;
+use tjaele_client::TjaeleClient;
+use tjaele_types::{DeviceState, GpuState};
+
+pub use curve_editor::CurveEditor;
+pub use trends::Trends;
 
 #[derive(Debug)]
 pub struct App {
+    client: TjaeleClient,
     pub latest_data: Result<MonitorData>,
     pub running: bool,
+    /// Index of GPU shown in the monitor
+    pub selected_device: usize,
+    /// Present in curve edit mode
+    pub curve_editor: Option<CurveEditor>,
+    pub trends: Trends,
+    /// Charts of recent telemetry are shown instead of the details
+    pub show_trends: bool,
+    /// Where the edited curve is saved
+    curve_file: PathBuf,
 }
 
 #[derive(Debug)]
@@ -25,63 +36,123 @@ pub struct MonitorData {
 }
 
 impl App {
-    pub async fn init() -> Result<Self> {
-        let latest_data = MonitorData::probe().await;
-
-        Ok(App { running: true, latest_data })
+    pub async fn init(client: TjaeleClient, curve_file: PathBuf) -> Result<Self> {
+        // an incompatible daemon is reported right away instead of failing to parse its state
+        let latest_data = match client.version().await {
+            Ok(_) => MonitorData::probe(&client).await,
+            Err(err) => Err(err.context("Failed to get tjaele data")),
+        };
+
+        let mut app = App {
+            client,
+            running: true,
+            latest_data,
+            selected_device: 0,
+            curve_editor: None,
+            trends: Trends::default(),
+            show_trends: false,
+            curve_file,
+        };
+        app.update_trends().await;
+
+        Ok(app)
     }
 
     pub async fn tick(&mut self) {
-        self.latest_data = MonitorData::probe().await;
+        self.latest_data = MonitorData::probe(&self.client).await;
+        self.selected_device = self.selected_device.min(self.device_count().saturating_sub(1));
+        self.update_trends().await;
+    }
+
+    async fn update_trends(&mut self) {
+        // daemon errors are already shown instead of the whole monitor
+        if self.latest_data.is_ok() {
+            let result = self.trends.update(&self.client).await;
+            self.trends.last_error = result.err().map(|err| format!("{err:#}"));
+        }
+    }
+
+    fn device_count(&self) -> usize {
+        self.latest_data.as_ref().map_or(0, |data| data.gpu_state.devices.len())
+    }
+
+    fn selected_device(&self) -> Option<&DeviceState> {
+        self.latest_data.as_ref().ok()?.gpu_state.devices.get(self.selected_device)
     }
 
     pub async fn handle_key_events(&mut self, key_event: KeyEvent) {
+        if self.curve_editor.is_some() {
+            self.handle_editor_key_events(key_event).await;
+            return;
+        }
+
         match key_event.code {
             KeyCode::Esc | KeyCode::Char('q') => {
                 self.running = false;
             },
+            KeyCode::Tab | KeyCode::Right => {
+                self.selected_device = (self.selected_device + 1) % self.device_count().max(1);
+            },
+            KeyCode::BackTab | KeyCode::Left => {
+                let device_count = self.device_count().max(1);
+                self.selected_device = (self.selected_device + device_count - 1) % device_count;
+            },
+            KeyCode::Char('e') => {
+                self.curve_editor = self.selected_device().map(CurveEditor::new);
+            },
+            KeyCode::Char('c') => self.show_trends = !self.show_trends,
+            KeyCode::Char('w') => self.trends.window = self.trends.window.next(),
+            _ => {},
+        }
+    }
+
+    async fn handle_editor_key_events(&mut self, key_event: KeyEvent) {
+        let Some(editor) = &mut self.curve_editor else {
+            return;
+        };
+        let shift = key_event.modifiers.contains(KeyModifiers::SHIFT);
+
+        match key_event.code {
+            KeyCode::Esc => self.curve_editor = None,
+            KeyCode::Left if shift => editor.move_temperature(-1),
+            KeyCode::Right if shift => editor.move_temperature(1),
+            KeyCode::Left => editor.select_previous(),
+            KeyCode::Right => editor.select_next(),
+            KeyCode::Up => editor.move_duty(1),
+            KeyCode::Down => editor.move_duty(-1),
+            KeyCode::Char('a') | KeyCode::Insert => editor.add_point(),
+            KeyCode::Char('d') | KeyCode::Delete => editor.delete_point(),
+            KeyCode::Enter => {
+                let result = match editor.update() {
+                    Ok(update) => self.client.set_fan_curve(&update).await,
+                    Err(err) => Err(err),
+                };
+                editor.message = Some(
+                    result
+                        .map(|()| "Fan curve applied by tjaeled".to_string())
+                        .map_err(|err| format!("{err:#}")),
+                );
+            },
+            KeyCode::Char('s') => {
+                editor.message = Some(
+                    editor
+                        .save(&self.curve_file)
+                        .map(|()| format!("Fan curve saved to {}", self.curve_file.display()))
+                        .map_err(|err| format!("{err:#}")),
+                );
+            },
             _ => {},
         }
     }
 }
 impl MonitorData {
-    pub async fn probe() -> Result<Self> {
+    pub async fn probe(client: &TjaeleClient) -> Result<Self> {
         let now = Instant::now();
 
-        let gpu_device_state = UdsClient::fetch_gpu_data()
-            .await
-            .context("Failed to get tjaele data, is control unit running?")?;
+        let gpu_device_state = client.gpu_state().await.context("Failed to get tjaele data")?;
 
         let elapsed = now.elapsed();
 
         Ok(MonitorData { gpu_state: gpu_device_state, latency: elapsed })
     }
 }
-
-#[derive(Debug)]
-pub struct UdsClient;
-
-impl UdsClient {
-    /// From Hyper client example
-    async fn fetch_gpu_data() -> Result<GpuState> {
-        let stream = UnixStream::connect(SOCKET).await?;
-        let io = TokioIo::new(stream);
-
-        let (mut sender, conn) = hyper::client::conn::http1::handshake(io).await?;
-
-        // Spawn a task to poll the connection, driving the HTTP state
-        tokio::task::spawn(async move {
-            // When this error occurs, the function errors
-            if (conn.await).is_err() {}
-        });
-
-        let req = Request::builder().uri("/gpustate").body(Empty::<Bytes>::new())?;
-        let res = sender.send_request(req).await?;
-        let body = res.collect().await?.aggregate();
-
-        // try to parse as json with serde_json
-        let gpu_state = serde_json::from_reader(body.reader())?;
-
-        Ok(gpu_state)
-    }
-}
@@ -0,0 +1,203 @@
+#![allow(clippy::cast_sign_loss)]
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tjaele_types::{validate_fan_curve, DeviceState, FanCurveUpdate};
+
+/// Anchor points of one GPU's fan curve being edited in the monitor
+#[derive(Debug, Clone)]
+pub struct CurveEditor {
+    /// UUID of the edited GPU
+    pub device: String,
+    /// Sorted by temperature, temperatures are unique
+    pub points: Vec<(u8, u8)>,
+    pub selected: usize,
+    /// Result of the last submit or save
+    pub message: Option<Result<String, String>>,
+}
+
+/// Config snippet written by `CurveEditor::save`
+#[derive(Serialize)]
+struct CurveFile<'a> {
+    device: [DeviceCurve<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct DeviceCurve<'a> {
+    id: &'a str,
+    fan_curve: &'a [(u8, u8)],
+}
+
+impl CurveEditor {
+    pub fn new(device: &DeviceState) -> Self {
+        let mut points = device.fan_curve_anchors.clone();
+        points.sort_unstable();
+
+        CurveEditor { device: device.persistent.uuid.clone(), points, selected: 0, message: None }
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.points.len().saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Temperature stays between the neighbouring points, so the order never changes
+    pub fn move_temperature(&mut self, delta: i16) {
+        let lower = match self.selected {
+            0 => 0,
+            i => self.points[i - 1].0.saturating_add(1),
+        };
+        let upper = self.points.get(self.selected + 1).map_or(u8::MAX, |point| point.0 - 1);
+
+        if let Some(point) = self.points.get_mut(self.selected) {
+            point.0 = i16::from(point.0)
+                .saturating_add(delta)
+                .clamp(i16::from(lower), i16::from(upper)) as u8;
+        }
+    }
+
+    pub fn move_duty(&mut self, delta: i16) {
+        if let Some(point) = self.points.get_mut(self.selected) {
+            point.1 = i16::from(point.1).saturating_add(delta).clamp(0, 100) as u8;
+        }
+    }
+
+    /// Inserts a point halfway to the next one, or 5C after the last one
+    pub fn add_point(&mut self) {
+        let Some(&(temp, duty)) = self.points.get(self.selected) else {
+            self.points.push((50, 50));
+            return;
+        };
+
+        let point = match self.points.get(self.selected + 1) {
+            Some(&(next_temp, next_duty)) if next_temp - temp > 1 => {
+                ((temp + next_temp) / 2, duty.midpoint(next_duty))
+            },
+            Some(_) => return,
+            None if temp < u8::MAX => (temp.saturating_add(5), duty),
+            None => return,
+        };
+
+        self.selected += 1;
+        self.points.insert(self.selected, point);
+    }
+
+    pub fn delete_point(&mut self) {
+        if self.selected < self.points.len() {
+            self.points.remove(self.selected);
+            self.selected = self.selected.min(self.points.len().saturating_sub(1));
+        }
+    }
+
+    /// Same rules as tjaeled applies to anchor points
+    pub fn validate(&self) -> Result<()> {
+        Ok(validate_fan_curve(&self.points)?)
+    }
+
+    /// Duty the edited curve gives at `temp`, interpolated the way tjaeled does it
+    pub fn duty_at(&self, temp: u8) -> Option<u8> {
+        let first = *self.points.first()?;
+        let last = *self.points.last()?;
+
+        if temp <= first.0 {
+            return Some(first.1);
+        }
+        if temp >= last.0 {
+            return Some(last.1);
+        }
+
+        let (lo, hi) = self
+            .points
+            .windows(2)
+            .map(|pair| (pair[0], pair[1]))
+            .find(|(lo, hi)| lo.0 <= temp && temp <= hi.0)?;
+
+        let slope = (f64::from(hi.1) - f64::from(lo.1)) / (f64::from(hi.0) - f64::from(lo.0));
+        let duty = f64::from(lo.1) + slope * (f64::from(temp) - f64::from(lo.0));
+        Some(duty.ceil().clamp(0.0, 100.0) as u8)
+    }
+
+    pub fn update(&self) -> Result<FanCurveUpdate> {
+        self.validate()?;
+        Ok(FanCurveUpdate { device: Some(self.device.clone()), fan_curve: self.points.clone() })
+    }
+
+    /// Writes the curve as a `[[device]]` table which can be pasted into the daemon config
+    pub fn save(&self, path: &Path) -> Result<()> {
+        self.validate()?;
+
+        let curve_file =
+            CurveFile { device: [DeviceCurve { id: &self.device, fan_curve: &self.points }] };
+        let toml = toml::to_string(&curve_file).context("Failed to serialize fan curve")?;
+        std::fs::write(path, toml)
+            .with_context(|| format!("Failed to write fan curve to {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tjaele_types::InvalidFanCurve;
+
+    use super::CurveEditor;
+
+    fn editor(points: &[(u8, u8)], selected: usize) -> CurveEditor {
+        CurveEditor {
+            device: "GPU-0".to_string(),
+            points: points.to_vec(),
+            selected,
+            message: None,
+        }
+    }
+
+    #[test]
+    fn temperature_stays_between_neighbouring_points() {
+        let mut editor = editor(&[(30, 30), (50, 50), (70, 80)], 1);
+        editor.move_temperature(-100);
+        assert_eq!(editor.points[1], (31, 50));
+        editor.move_temperature(100);
+        assert_eq!(editor.points[1], (69, 50));
+
+        editor.selected = 2;
+        editor.move_temperature(i16::MAX);
+        assert_eq!(editor.points[2], (u8::MAX, 80));
+    }
+
+    #[test]
+    fn adds_points_halfway_or_after_last_one() {
+        let mut editor = editor(&[(30, 30), (50, 51)], 0);
+        editor.add_point();
+        assert_eq!(editor.points, [(30, 30), (40, 40), (50, 51)]);
+        assert_eq!(editor.selected, 1);
+
+        editor.selected = 2;
+        editor.add_point();
+        assert_eq!(editor.points, [(30, 30), (40, 40), (50, 51), (55, 51)]);
+        assert_eq!(editor.selected, 3);
+    }
+
+    #[test]
+    fn does_not_add_points_without_room() {
+        let mut editor = editor(&[(30, 30), (31, 40), (u8::MAX, 100)], 0);
+        editor.add_point();
+        editor.selected = 2;
+        editor.add_point();
+        assert_eq!(editor.points, [(30, 30), (31, 40), (u8::MAX, 100)]);
+    }
+
+    #[test]
+    fn validates_with_rules_of_tjaeled() {
+        let invalid = |points: &[(u8, u8)]| {
+            editor(points, 0).validate().unwrap_err().downcast::<InvalidFanCurve>().unwrap()
+        };
+
+        assert!(editor(&[(30, 30), (50, 50), (70, 100)], 0).validate().is_ok());
+        assert_eq!(invalid(&[(30, 30), (70, 100)]), InvalidFanCurve::TooFewPoints);
+        assert_eq!(invalid(&[(30, 30), (50, 50), (70, 101)]), InvalidFanCurve::DutyTooHigh);
+        assert_eq!(invalid(&[(30, 30), (50, 60), (70, 50)]), InvalidFanCurve::DecreasingDuty);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, TimeDelta};
+use tjaele_client::TjaeleClient;
+use tjaele_types::HistorySample;
+
+/// Time shown in the trend charts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, derive_more::Display)]
+pub enum TrendWindow {
+    #[display("1m")]
+    OneMinute,
+    #[default]
+    #[display("5m")]
+    FiveMinutes,
+    #[display("30m")]
+    ThirtyMinutes,
+}
+
+impl TrendWindow {
+    pub fn duration(self) -> TimeDelta {
+        match self {
+            TrendWindow::OneMinute => TimeDelta::minutes(1),
+            TrendWindow::FiveMinutes => TimeDelta::minutes(5),
+            TrendWindow::ThirtyMinutes => TimeDelta::minutes(30),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            TrendWindow::OneMinute => TrendWindow::FiveMinutes,
+            TrendWindow::FiveMinutes => TrendWindow::ThirtyMinutes,
+            TrendWindow::ThirtyMinutes => TrendWindow::OneMinute,
+        }
+    }
+}
+
+/// Telemetry of recent minutes, fetched from the daemon history
+/// and kept for the longest window
+#[derive(Debug, Default)]
+pub struct Trends {
+    pub window: TrendWindow,
+    /// Set when the last update failed, older samples are still shown
+    pub last_error: Option<String>,
+    /// By GPU index, oldest samples first
+    samples: BTreeMap<usize, VecDeque<HistorySample>>,
+}
+
+impl Trends {
+    /// Fetches samples taken since the last update
+    pub async fn update(&mut self, client: &TjaeleClient) -> Result<()> {
+        let since = self
+            .samples
+            .values()
+            .filter_map(|samples| samples.back().map(|sample| sample.time))
+            .min()
+            .unwrap_or_else(|| Local::now() - TrendWindow::ThirtyMinutes.duration());
+
+        let history = client.history(Some(since), &[]).await?;
+
+        for device in history.devices {
+            let samples = self.samples.entry(device.index).or_default();
+            let last_time = samples.back().map(|sample| sample.time);
+            // other GPUs may have been behind, so some samples can be fetched twice
+            samples.extend(
+                device
+                    .samples
+                    .into_iter()
+                    .filter(|sample| last_time.is_none_or(|t| sample.time > t)),
+            );
+
+            let Some(newest) = samples.back().map(|sample| sample.time) else {
+                continue;
+            };
+            let oldest_kept = newest - TrendWindow::ThirtyMinutes.duration();
+            while samples.front().is_some_and(|sample| sample.time < oldest_kept) {
+                samples.pop_front();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Samples of the GPU within the window, with their age relative to the newest one
+    pub fn window_samples(&self, device_idx: usize) -> Vec<(f64, &HistorySample)> {
+        let Some(samples) = self.samples.get(&device_idx) else {
+            return Vec::new();
+        };
+        let Some(newest) = samples.back().map(|sample| sample.time) else {
+            return Vec::new();
+        };
+        let oldest_shown = newest - self.window.duration();
+
+        samples
+            .iter()
+            .filter(|sample| sample.time >= oldest_shown)
+            .map(|sample| (seconds_between(sample.time, newest), sample))
+            .collect()
+    }
+}
+
+/// Negative for samples older than `newest`
+fn seconds_between(time: DateTime<Local>, newest: DateTime<Local>) -> f64 {
+    (time - newest).num_milliseconds() as f64 / 1000.0
+}
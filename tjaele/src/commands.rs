@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use tjaele_client::{DaemonUnavailable, TjaeleClient};
+use tjaele_types::api::{ApiError, ApiErrorKind};
+use tjaele_types::{ControlMode, DaemonEvent, DeviceState, DutyOverrideRequest};
+
+pub async fn set_duty(
+    client: &TjaeleClient,
+    duty: u8,
+    duration: Option<Duration>,
+    fans: Option<Vec<usize>>,
+    device: Option<String>,
+) -> Result<()> {
+    let request = DutyOverrideRequest { device, duty, fans, duration };
+    client.set_duty_override(&request).await.context("Failed to set fan duty")?;
+
+    match duration {
+        Some(duration) => println!("Fan duty fixed at {duty}% for {}s", duration.as_secs_f64()),
+        None => println!("Fan duty fixed at {duty}% until reset"),
+    }
+
+    Ok(())
+}
+
+pub async fn reset_duty(client: &TjaeleClient) -> Result<()> {
+    client.clear_duty_overrides().await.context("Failed to reset fan duty")?;
+    println!("Fans returned to curve control");
+
+    Ok(())
+}
+
+pub async fn clear_alarms(client: &TjaeleClient) -> Result<()> {
+    client.clear_fan_alarms().await.context("Failed to clear fan alarms")?;
+    println!("Fan alarms cleared");
+
+    Ok(())
+}
+
+/// Parses durations like `90`, `90s`, `10m` or `1.5h`
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, 's')) => (&value[..i], 1.0),
+        Some((i, 'm')) => (&value[..i], 60.0),
+        Some((i, 'h')) => (&value[..i], 3600.0),
+        _ => (value, 1.0),
+    };
+
+    let number = number.trim().parse::<f64>().map_err(|err| format!("{err}"))?;
+    Duration::try_from_secs_f64(number * multiplier).map_err(|err| format!("{err}"))
+}
+
+/// Exit codes (from `sysexits.h`) telling scripts why a command failed
+pub mod exit_code {
+    /// Request rejected by the daemon, eg. invalid curve
+    pub const DATA_ERROR: u8 = 65;
+    /// Daemon is not running or does not respond
+    pub const UNAVAILABLE: u8 = 69;
+    /// Daemon failed to handle the request
+    pub const SOFTWARE: u8 = 70;
+    /// Daemon speaks another API version
+    pub const PROTOCOL: u8 = 76;
+    /// Only root can modify fan control
+    pub const NO_PERMISSION: u8 = 77;
+}
+
+/// Exit code for a failed command, `1` when the cause is not known
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    // downcasting looks through contexts added on the way up
+    if let Some(unavailable) = err.downcast_ref::<DaemonUnavailable>() {
+        return match unavailable {
+            DaemonUnavailable::Incompatible { .. } => exit_code::PROTOCOL,
+            DaemonUnavailable::Unreachable(_) | DaemonUnavailable::TimedOut(_) => {
+                exit_code::UNAVAILABLE
+            },
+        };
+    }
+
+    match err.downcast_ref::<ApiError>().map(|api_error| api_error.kind) {
+        Some(ApiErrorKind::InvalidRequest) => exit_code::DATA_ERROR,
+        Some(ApiErrorKind::Forbidden) => exit_code::NO_PERMISSION,
+        Some(ApiErrorKind::NotFound | ApiErrorKind::Internal) => exit_code::SOFTWARE,
+        None => 1,
+    }
+}
+
+/// Short human readable summary of every GPU
+pub async fn status(client: &TjaeleClient) -> Result<()> {
+    let gpu_state = client.gpu_state().await.context("Failed to get GPU state")?;
+
+    for device in &gpu_state.devices {
+        let persistent = &device.persistent;
+        let runtime = &device.runtime;
+        println!("GPU {}: {} ({})", persistent.index, persistent.device_name, persistent.uuid);
+
+        let memory_temperature = runtime
+            .memory_temperature
+            .map(|temperature| format!(", memory {temperature}C"))
+            .unwrap_or_default();
+        println!("  Temperature  {}C{memory_temperature}", runtime.device_temperature);
+        match runtime.power_limit {
+            Some(power_limit) => {
+                println!("  Power        {:.1} / {power_limit:.1} W", runtime.power_usage);
+            },
+            None => println!("  Power        {:.1} W", runtime.power_usage),
+        }
+
+        for fan in &runtime.fan_states {
+            println!(
+                "  Fan {}        {}% duty, {}% speed ({})",
+                fan.index, fan.duty, fan.speed, fan.control_policy
+            );
+        }
+
+        println!("  Control      {}", control_status(device));
+    }
+
+    Ok(())
+}
+
+/// Same as the status block of the monitor, without colours
+fn control_status(device: &DeviceState) -> String {
+    if let Some(degraded) = &device.degraded {
+        return format!(
+            "DEGRADED ({} failures, retrying): {}",
+            degraded.failures, degraded.last_error
+        );
+    }
+
+    if let Some(event) = device.safety_events.last().filter(|event| event.ended.is_none()) {
+        return format!("THERMAL EMERGENCY ({})", event.reaction);
+    }
+
+    let mut status = format!("{} mode", device.control_mode);
+    if let Some(duty_override) = &device.duty_override {
+        status.push_str(&format!(", duty fixed at {}%", duty_override.duty));
+    }
+    for alarm in &device.fan_alarms {
+        status.push_str(&format!(", fan {} stalled", alarm.fan));
+    }
+    if device.interference_events > 0 {
+        status.push_str(&format!(", {} external fan changes", device.interference_events));
+    }
+
+    status
+}
+
+/// Whole state as JSON, or as `name value` lines which are easy to grep
+pub async fn get(client: &TjaeleClient, json: bool) -> Result<()> {
+    let gpu_state = client.gpu_state().await.context("Failed to get GPU state")?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&gpu_state)?);
+        return Ok(());
+    }
+
+    for device in &gpu_state.devices {
+        let runtime = &device.runtime;
+        let gpu = format!("gpu{}", device.persistent.index);
+
+        println!("{gpu}.name {}", device.persistent.device_name);
+        println!("{gpu}.uuid {}", device.persistent.uuid);
+        println!("{gpu}.temperature {}", runtime.device_temperature);
+        if let Some(temperature) = runtime.memory_temperature {
+            println!("{gpu}.memory_temperature {temperature}");
+        }
+        println!("{gpu}.power {}", runtime.power_usage);
+        if let Some(power_limit) = runtime.power_limit {
+            println!("{gpu}.power_limit {power_limit}");
+        }
+        println!("{gpu}.clocks.graphics {}", runtime.clock_speeds.graphics);
+        println!("{gpu}.clocks.memory {}", runtime.clock_speeds.memory);
+        println!("{gpu}.memory.used {}", runtime.memory_info.used);
+        println!("{gpu}.memory.total {}", runtime.memory_info.total);
+        for fan in &runtime.fan_states {
+            println!("{gpu}.fan{}.duty {}", fan.index, fan.duty);
+            println!("{gpu}.fan{}.speed {}", fan.index, fan.speed);
+        }
+        println!("{gpu}.control_mode {}", device.control_mode);
+        println!("{gpu}.degraded {}", device.degraded.is_some());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum WatchFormat {
+    /// One line per GPU
+    #[default]
+    Text,
+    /// One row per fan, with a header
+    Csv,
+}
+
+/// Prints a line whenever the daemon reports new state, until it stops
+pub async fn watch(client: &TjaeleClient, format: WatchFormat) -> Result<()> {
+    let mut events = Box::pin(client.events().await.context("Failed to subscribe to events")?);
+
+    if let WatchFormat::Csv = format {
+        println!("time,gpu,fan,temperature,power,duty,speed");
+    }
+
+    while let Some(event) = events.next().await {
+        let DaemonEvent::State(device) = event? else {
+            continue;
+        };
+        let runtime = &device.runtime;
+        let time = runtime.probe_time;
+
+        match format {
+            WatchFormat::Text => {
+                let fans = runtime
+                    .fan_states
+                    .iter()
+                    .map(|fan| format!("fan {} {}% ({}%)", fan.index, fan.duty, fan.speed))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                println!(
+                    "{} GPU {} {}C {:.1}W {fans}",
+                    time.format("%H:%M:%S"),
+                    device.persistent.index,
+                    runtime.device_temperature,
+                    runtime.power_usage
+                );
+            },
+            WatchFormat::Csv => {
+                for fan in &runtime.fan_states {
+                    println!(
+                        "{},{},{},{},{},{},{}",
+                        time.to_rfc3339(),
+                        device.persistent.index,
+                        fan.index,
+                        runtime.device_temperature,
+                        runtime.power_usage,
+                        fan.duty,
+                        fan.speed
+                    );
+                }
+            },
+        }
+    }
+
+    bail!("tjaeled closed the event stream")
+}
+
+/// Active fan curve of every GPU in 5C steps, the current temperature is marked
+pub async fn show_curve(client: &TjaeleClient) -> Result<()> {
+    let gpu_state = client.gpu_state().await.context("Failed to get GPU state")?;
+
+    for device in &gpu_state.devices {
+        println!("GPU {}: {}", device.persistent.index, device.persistent.device_name);
+        if device.control_mode != ControlMode::Curve {
+            println!("  (not used, GPU is in {} mode)", device.control_mode);
+        }
+
+        let curve = device.fan_curve.iter().copied().collect::<BTreeMap<_, _>>();
+        let temperature = device.runtime.device_temperature;
+
+        println!("  Temperature  Duty");
+        for step in (0..=100).step_by(5) {
+            let Some(duty) = curve.get(&step) else {
+                continue;
+            };
+            let current = (u32::from(step)..u32::from(step) + 5).contains(&temperature);
+            let marker = if current { format!("  <- {temperature}C now") } else { String::new() };
+            println!("  {step:>9}C  {duty:>3}%{marker}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use anyhow::{anyhow, Context};
+    use tjaele_client::DaemonUnavailable;
+    use tjaele_types::api::{ApiError, ApiErrorKind, Compatibility};
+
+    use super::{exit_code, parse_duration};
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for value in ["", "m", "ten", "10d", "-5", "1.5.h"] {
+            assert!(parse_duration(value).is_err(), "{value:?} was accepted");
+        }
+    }
+
+    fn api_error(kind: ApiErrorKind) -> anyhow::Error {
+        let api_error = ApiError { kind, chain: vec!["Rejected".to_string()] };
+        // as returned by the client and commands
+        anyhow::Error::new(api_error)
+            .context("tjaeled responded with 400 Bad Request")
+            .context("Failed to set fan duty")
+    }
+
+    #[test]
+    fn maps_api_errors_to_exit_codes() {
+        assert_eq!(exit_code(&api_error(ApiErrorKind::InvalidRequest)), exit_code::DATA_ERROR);
+        assert_eq!(exit_code(&api_error(ApiErrorKind::Forbidden)), exit_code::NO_PERMISSION);
+        assert_eq!(exit_code(&api_error(ApiErrorKind::NotFound)), exit_code::SOFTWARE);
+        assert_eq!(exit_code(&api_error(ApiErrorKind::Internal)), exit_code::SOFTWARE);
+    }
+
+    #[test]
+    fn maps_unavailable_daemon_to_exit_codes() {
+        let unreachable = Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+            .context(DaemonUnavailable::Unreachable(PathBuf::from("/run/tjaeled.sock")))
+            .context("Failed to get GPU state")
+            .unwrap_err();
+        assert_eq!(exit_code(&unreachable), exit_code::UNAVAILABLE);
+
+        let timed_out = anyhow::Error::new(DaemonUnavailable::TimedOut(Duration::from_secs(5)));
+        assert_eq!(exit_code(&timed_out), exit_code::UNAVAILABLE);
+
+        for compatibility in [Compatibility::DaemonTooOld, Compatibility::DaemonTooNew] {
+            let incompatible = anyhow::Error::new(DaemonUnavailable::Incompatible {
+                compatibility,
+                api_version: None,
+            });
+            assert_eq!(exit_code(&incompatible), exit_code::PROTOCOL);
+        }
+    }
+
+    #[test]
+    fn unknown_errors_exit_with_one() {
+        assert_eq!(exit_code(&anyhow!("Something else failed")), 1);
+    }
+}
@@ -1,12 +1,20 @@
 //! Heavily inspied by `simple-async` form https://github.com/ratatui/templates
 
 mod app;
+mod commands;
 mod tui;
 
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
 use anyhow::{ensure, Result};
 use app::App;
-use clap::{command, Parser};
-use tui::{Event, Tui};
+use clap::{Parser, Subcommand};
+use commands::WatchFormat;
+use tjaele_client::{ClientConfig, TjaeleClient};
+use tjaele_types::SOCKET;
+use tui::{Event, Tui, TuiConfig};
 
 #[derive(Parser)]
 #[command(version, about = "Nvidia Fan Control for Wayland", long_about = "long about")]
@@ -14,20 +22,110 @@ struct Cli {
     /// Monitor refresh interval in seconds
     #[arg(short, long, default_value_t = 2.0)]
     refresh_interval: f64,
+    /// Path to the Unix socket of tjaeled
+    #[arg(short, long, global = true, default_value = SOCKET)]
+    socket_path: PathBuf,
+    /// File the fan curve edited in the monitor is saved to
+    #[arg(long, default_value = "fan_curve.toml")]
+    curve_file: PathBuf,
+    /// Monitor layout and theme, `~/.config/tjaele/tui.toml` when not provided
+    #[arg(long)]
+    tui_config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Pin fans at a fixed duty instead of the fan curve (requires root)
+    SetDuty {
+        /// Fan duty in percent
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        duty: u8,
+        /// Return to curve control after given time (eg. 90s, 10m, 1h)
+        #[arg(long = "for", value_parser = commands::parse_duration)]
+        duration: Option<Duration>,
+        /// Indices of fans to pin, all fans when not provided
+        #[arg(long, value_delimiter = ',')]
+        fans: Option<Vec<usize>>,
+        /// UUID or PCI bus id of the GPU, all GPUs when not provided
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Return all fans to curve control (requires root)
+    ResetDuty,
+    /// Acknowledge fan stall alarms and stop reacting to them (requires root)
+    ClearAlarms,
+    /// Print a short summary of every GPU
+    Status,
+    /// Print the whole state reported by tjaeled
+    Get {
+        /// Print state as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a line whenever tjaeled reports new state, until interrupted
+    Watch {
+        #[arg(long, value_enum, default_value_t)]
+        format: WatchFormat,
+    },
+    /// Inspect the fan curve
+    Curve {
+        #[command(subcommand)]
+        command: CurveCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum CurveCommand {
+    /// Print the active fan curve of every GPU as a table
+    Show,
 }
 
+/// Exits with codes from `commands::exit_code` when a command fails
 #[tokio::main(worker_threads = 4)]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
+    let client =
+        TjaeleClient::new(ClientConfig { socket_path: cli.socket_path, ..Default::default() });
 
+    let result = match cli.command {
+        None => run_monitor(client, cli.refresh_interval, cli.curve_file, cli.tui_config).await,
+        Some(Command::SetDuty { duty, duration, fans, device }) => {
+            commands::set_duty(&client, duty, duration, fans, device).await
+        },
+        Some(Command::ResetDuty) => commands::reset_duty(&client).await,
+        Some(Command::ClearAlarms) => commands::clear_alarms(&client).await,
+        Some(Command::Status) => commands::status(&client).await,
+        Some(Command::Get { json }) => commands::get(&client, json).await,
+        Some(Command::Watch { format }) => commands::watch(&client, format).await,
+        Some(Command::Curve { command: CurveCommand::Show }) => commands::show_curve(&client).await,
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(commands::exit_code(&err))
+        },
+    }
+}
+
+async fn run_monitor(
+    client: TjaeleClient,
+    refresh_interval: f64,
+    curve_file: PathBuf,
+    tui_config: Option<PathBuf>,
+) -> Result<()> {
     ensure!(
-        cli.refresh_interval > 0.1 && cli.refresh_interval <= 10.0,
+        refresh_interval > 0.1 && refresh_interval <= 10.0,
         "Monitor refresh interval must be between 0.1 and 10 secods"
     );
 
-    let mut app = App::init().await?;
+    let tui_config = TuiConfig::load(tui_config.as_deref())?;
+    let mut app = App::init(client, curve_file).await?;
     let terminal = ratatui::try_init()?;
-    let mut tui = Tui::new(terminal, cli.refresh_interval);
+    let mut tui = Tui::new(terminal, refresh_interval, tui_config);
 
     while app.running {
         tui.draw(&app)?;
@@ -1,84 +1,204 @@
-use crate::app::{App, MonitorData};
+use crate::app::{App, MonitorData, Trends};
 
+mod config;
 mod events;
+mod theme;
+mod trend_charts;
 mod tui_blocks;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use events::EventHandler;
 use ratatui::{
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     DefaultTerminal, Frame,
 };
+use tjaele_types::DeviceState;
+use trend_charts::render_trend_charts;
 
 use tui_blocks::{
-    render_cooling_chart, render_fans_table, DeviceBlock, DriverBlock, ErrorBlock, SpecsBlock,
-    TemperatureBlock, TimeBlock,
+    render_cooling_chart, render_curve_editor, render_fans_table, DeviceBlock, DriverBlock,
+    ErrorBlock, SpecsBlock, SummaryBlock, TemperatureBlock, TimeBlock,
 };
 
+use config::{Panel, TemperatureUnit};
+use theme::Theme;
+
+pub use config::TuiConfig;
 pub use events::Event;
 
+/// Panels of the top row, next to each other
+const HEADER_PANELS: [Panel; 3] = [Panel::Time, Panel::Device, Panel::Driver];
+/// Panels of the left column, below each other
+const COOLER_PANELS: [Panel; 3] = [Panel::Temperatures, Panel::Fans, Panel::FanCurve];
+
 #[derive(Debug)]
 pub struct Tui {
     terminal: DefaultTerminal,
     pub events: EventHandler,
+    config: TuiConfig,
+    theme: Theme,
 }
 
 impl Tui {
-    pub fn new(terminal: DefaultTerminal, tick_rate: f64) -> Self {
-        Tui { terminal, events: EventHandler::new(tick_rate) }
+    pub fn new(terminal: DefaultTerminal, tick_rate: f64, config: TuiConfig) -> Self {
+        let theme = Theme::new(config.theme);
+        Tui { terminal, events: EventHandler::new(tick_rate), config, theme }
     }
 
     pub fn draw(&mut self, app: &App) -> Result<()> {
-        self.terminal.draw(|frame| Tui::draw_frame(frame, app))?;
+        self.terminal.draw(|frame| Tui::draw_frame(frame, app, &self.config, &self.theme))?;
         Ok(())
     }
 
-    fn draw_frame(frame: &mut Frame, app: &App) {
+    fn draw_frame(frame: &mut Frame, app: &App, config: &TuiConfig, theme: &Theme) {
+        let view = View { app, config, theme };
+
         match &app.latest_data {
-            Ok(data) => Tui::draw_normal_frame(frame, data),
-            Err(err) => Tui::draw_error_frame(frame, err),
+            Ok(data) => match data.gpu_state.devices.get(app.selected_device) {
+                Some(device) if config.is_compact(frame.area()) => {
+                    view.draw_compact_frame(frame, data, device);
+                },
+                Some(device) => view.draw_normal_frame(frame, data, device),
+                None => view.draw_error_frame(frame, &anyhow!("tjaeled did not report any GPU")),
+            },
+            Err(err) => view.draw_error_frame(frame, err),
         }
     }
+}
+
+/// What a frame is drawn from
+struct View<'a> {
+    app: &'a App,
+    config: &'a TuiConfig,
+    theme: &'a Theme,
+}
+
+impl View<'_> {
+    fn draw_normal_frame(&self, frame: &mut Frame, data: &MonitorData, device: &DeviceState) {
+        let header_panels = self.config.panels_of(&HEADER_PANELS);
+        let header_height = if header_panels.is_empty() { 0 } else { 10 };
 
-    fn draw_normal_frame(frame: &mut Frame, data: &MonitorData) {
         let main_layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Length(10), Constraint::Fill(1)])
+            .constraints(vec![Constraint::Length(header_height), Constraint::Fill(1)])
             .split(frame.area());
 
+        let header_count = header_panels.len() as u32;
         let upper_layout = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints(vec![
-                Constraint::Ratio(1, 3),
-                Constraint::Ratio(1, 3),
-                Constraint::Ratio(1, 3),
-            ])
+            .constraints(header_panels.iter().map(|_| Constraint::Ratio(1, header_count)))
             .split(main_layout[0]);
+        for (panel, area) in header_panels.iter().zip(upper_layout.iter()) {
+            self.render_panel(frame, *panel, data, device, *area);
+        }
+
+        // editor and charts need the whole lower half to be usable
+        if self.render_lower_mode(frame, device, main_layout[1]) {
+            return;
+        }
+
+        let cooler_panels = self.config.panels_of(&COOLER_PANELS);
+        let show_specs = self.config.shows(Panel::Specs);
 
+        // a missing column leaves the whole width to the other one
         let lower_layout = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+            .constraints(match (cooler_panels.is_empty(), show_specs) {
+                (false, true) => vec![Constraint::Percentage(50), Constraint::Percentage(50)],
+                (false, false) => vec![Constraint::Percentage(100), Constraint::Percentage(0)],
+                (true, _) => vec![Constraint::Percentage(0), Constraint::Percentage(100)],
+            })
             .split(main_layout[1]);
 
-        let cooler_layout = Layout::default()
+        self.render_column(frame, &cooler_panels, data, device, lower_layout[0]);
+        if show_specs {
+            self.render_panel(frame, Panel::Specs, data, device, lower_layout[1]);
+        }
+    }
+
+    /// Summary instead of the top row and only fans and the fan curve below it
+    fn draw_compact_frame(&self, frame: &mut Frame, data: &MonitorData, device: &DeviceState) {
+        let main_layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(vec![
-                Constraint::Length(4),
-                Constraint::Length(data.gpu_state.persistent.num_fans as u16 + 3),
-                Constraint::Fill(1),
-            ])
-            .split(lower_layout[0]);
-
-        frame.render_widget(TimeBlock { data }, upper_layout[0]);
-        frame.render_widget(DeviceBlock { data }, upper_layout[1]);
-        frame.render_widget(DriverBlock { data }, upper_layout[2]);
-        frame.render_widget(TemperatureBlock { data }, cooler_layout[0]);
-        render_fans_table(frame, data, cooler_layout[1]);
-        render_cooling_chart(frame, data, cooler_layout[2]);
-        frame.render_widget(SpecsBlock { data }, lower_layout[1]);
+            .constraints(vec![Constraint::Length(4), Constraint::Fill(1)])
+            .split(frame.area());
+
+        let summary =
+            SummaryBlock { data, device, theme: self.theme, unit: self.config.temperature_unit };
+        frame.render_widget(summary, main_layout[0]);
+
+        if self.render_lower_mode(frame, device, main_layout[1]) {
+            return;
+        }
+
+        let panels = self.config.panels_of(&[Panel::Fans, Panel::FanCurve]);
+        self.render_column(frame, &panels, data, device, main_layout[1]);
     }
 
-    fn draw_error_frame(frame: &mut Frame, error: &anyhow::Error) {
-        frame.render_widget(ErrorBlock { error }, frame.area());
+    fn draw_error_frame(&self, frame: &mut Frame, error: &anyhow::Error) {
+        frame.render_widget(ErrorBlock { error, theme: self.theme }, frame.area());
+    }
+
+    /// Renders the curve editor or trend charts, if either is open
+    fn render_lower_mode(&self, frame: &mut Frame, device: &DeviceState, area: Rect) -> bool {
+        let unit = self.config.temperature_unit;
+
+        if let Some(editor) = &self.app.curve_editor {
+            render_curve_editor(frame, device, editor, self.theme, unit, area);
+            return true;
+        }
+        if self.app.show_trends {
+            render_trend_charts(frame, device, &self.app.trends, self.theme, unit, area);
+            return true;
+        }
+
+        false
+    }
+
+    /// Panels below each other, the fan curve takes the space left
+    fn render_column(
+        &self,
+        frame: &mut Frame,
+        panels: &[Panel],
+        data: &MonitorData,
+        device: &DeviceState,
+        area: Rect,
+    ) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(panels.iter().map(|panel| match panel {
+                Panel::Temperatures => Constraint::Length(4),
+                Panel::Fans => Constraint::Length(device.persistent.num_fans as u16 + 3),
+                _ => Constraint::Fill(1),
+            }))
+            .split(area);
+
+        for (panel, area) in panels.iter().zip(layout.iter()) {
+            self.render_panel(frame, *panel, data, device, *area);
+        }
+    }
+
+    fn render_panel(
+        &self,
+        frame: &mut Frame,
+        panel: Panel,
+        data: &MonitorData,
+        device: &DeviceState,
+        area: Rect,
+    ) {
+        let theme = self.theme;
+        let unit = self.config.temperature_unit;
+
+        match panel {
+            Panel::Time => frame.render_widget(TimeBlock { data, device, theme }, area),
+            Panel::Device => frame.render_widget(DeviceBlock { device, theme }, area),
+            Panel::Driver => frame.render_widget(DriverBlock { device, theme }, area),
+            Panel::Temperatures => {
+                frame.render_widget(TemperatureBlock { device, theme, unit }, area);
+            },
+            Panel::Fans => render_fans_table(frame, device, theme, area),
+            Panel::FanCurve => render_cooling_chart(frame, device, theme, unit, area),
+            Panel::Specs => frame.render_widget(SpecsBlock { device, theme, unit }, area),
+        }
     }
 }
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ratatui::layout::Rect;
+use serde::Deserialize;
+
+use super::theme::ThemeName;
+
+/// Look of the monitor, read from `tui.toml`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TuiConfig {
+    pub theme: ThemeName,
+    pub temperature_unit: TemperatureUnit,
+    /// Shown in this order within their part of the screen
+    pub panels: Vec<Panel>,
+    /// Compact layout is used when the terminal is narrower or lower than this
+    pub compact_below: TerminalSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Panel {
+    Time,
+    Device,
+    Driver,
+    Temperatures,
+    Fans,
+    FanCurve,
+    Specs,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TerminalSize {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        TuiConfig {
+            theme: ThemeName::default(),
+            temperature_unit: TemperatureUnit::default(),
+            panels: vec![
+                Panel::Time,
+                Panel::Device,
+                Panel::Driver,
+                Panel::Temperatures,
+                Panel::Fans,
+                Panel::FanCurve,
+                Panel::Specs,
+            ],
+            compact_below: TerminalSize { width: 120, height: 32 },
+        }
+    }
+}
+
+impl TuiConfig {
+    /// Reads `path`, or `tui.toml` in the user config directory when not provided,
+    /// in which case a missing file means the defaults
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        if let Some(path) = path {
+            return Self::new_from_file(path);
+        }
+
+        match default_path() {
+            Some(path) if path.exists() => Self::new_from_file(&path),
+            _ => Ok(TuiConfig::default()),
+        }
+    }
+
+    fn new_from_file(path: &Path) -> Result<Self> {
+        let cfg = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read TUI config from {}", path.display()))?;
+        toml::from_str(&cfg)
+            .with_context(|| format!("Failed to parse TUI config from {}", path.display()))
+    }
+
+    pub fn shows(&self, panel: Panel) -> bool {
+        self.panels.contains(&panel)
+    }
+
+    /// Shown panels out of `group`, in config order
+    pub fn panels_of(&self, group: &[Panel]) -> Vec<Panel> {
+        self.panels.iter().copied().filter(|panel| group.contains(panel)).collect()
+    }
+
+    pub fn is_compact(&self, area: Rect) -> bool {
+        area.width < self.compact_below.width || area.height < self.compact_below.height
+    }
+}
+
+/// `$XDG_CONFIG_HOME/tjaele/tui.toml`, falling back to `~/.config`
+fn default_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_dir.join("tjaele").join("tui.toml"))
+}
+
+impl TemperatureUnit {
+    /// From Celsius, in which tjaeled reports all temperatures
+    pub fn convert(self, celsius: f64) -> f64 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "C",
+            TemperatureUnit::Fahrenheit => "F",
+        }
+    }
+
+    /// Rounded to whole degrees, with the unit
+    pub fn format(self, celsius: impl Into<f64>) -> String {
+        format!("{:.0} {}", self.convert(celsius.into()), self.symbol())
+    }
+}
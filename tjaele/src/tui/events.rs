@@ -50,10 +50,8 @@ impl EventHandler {
                   }
                   Some(Ok(evt)) = crossterm_event => {
                     match evt {
-                      CrosstermEvent::Key(key) => {
-                        if key.kind == crossterm::event::KeyEventKind::Press {
-                          sender.send(Event::Key(key)).unwrap();
-                        }
+                      CrosstermEvent::Key(key) if key.kind == crossterm::event::KeyEventKind::Press => {
+                        sender.send(Event::Key(key)).unwrap();
                       },
                       CrosstermEvent::Resize(_, _) => {
                         sender.send(Event::DrawTrigger).unwrap();
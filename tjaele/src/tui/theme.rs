@@ -0,0 +1,115 @@
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeName {
+    #[default]
+    Default,
+    /// Bold and saturated colours only, readable on light and dark terminals
+    HighContrast,
+    /// Terminal colours, with text attributes instead
+    NoColor,
+}
+
+/// Styles used across the monitor
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// Labels above values and table headers
+    pub label: Style,
+    /// Fan curve and single series in charts
+    pub primary: Style,
+    /// Fan speeds next to the fan curve and the preview in the editor
+    pub secondary: Style,
+    /// Current curve behind the edited one
+    pub muted: Style,
+    /// Anchor points in the editor
+    pub marker: Style,
+    /// Selected anchor point
+    pub highlight: Style,
+    pub axis: Style,
+    pub ok: Style,
+    pub warning: Style,
+    pub alert: Style,
+    /// Fans and clocks in trend charts, in order
+    series: Vec<Style>,
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Default => Theme::default_colors(),
+            ThemeName::HighContrast => Theme::high_contrast(),
+            ThemeName::NoColor => Theme::no_color(),
+        }
+    }
+
+    fn default_colors() -> Self {
+        Theme {
+            label: Style::new().fg(Color::Yellow),
+            primary: Style::new().fg(Color::Yellow),
+            secondary: Style::new().fg(Color::Blue),
+            muted: Style::new().fg(Color::DarkGray),
+            marker: Style::new().fg(Color::White),
+            highlight: Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+            axis: Style::new().fg(Color::Gray),
+            ok: Style::new().fg(Color::Green),
+            warning: Style::new().fg(Color::Red),
+            alert: Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+            series: [
+                Color::Yellow,
+                Color::Cyan,
+                Color::Magenta,
+                Color::Green,
+                Color::Red,
+                Color::Blue,
+            ]
+            .map(|color| Style::new().fg(color))
+            .to_vec(),
+        }
+    }
+
+    /// Yellow, white and grey disappear on light backgrounds, so none are used
+    fn high_contrast() -> Self {
+        let bold = Style::new().add_modifier(Modifier::BOLD);
+
+        Theme {
+            label: bold.fg(Color::Blue),
+            primary: bold.fg(Color::Magenta),
+            secondary: bold.fg(Color::Blue),
+            muted: Style::new(),
+            marker: bold,
+            highlight: bold.fg(Color::Red).add_modifier(Modifier::REVERSED),
+            axis: Style::new(),
+            ok: bold.fg(Color::Green),
+            warning: bold.fg(Color::Red),
+            alert: bold.fg(Color::Red).add_modifier(Modifier::REVERSED),
+            series: [Color::Magenta, Color::Blue, Color::Red, Color::Green]
+                .map(|color| bold.fg(color))
+                .to_vec(),
+        }
+    }
+
+    fn no_color() -> Self {
+        let bold = Style::new().add_modifier(Modifier::BOLD);
+
+        Theme {
+            label: bold.add_modifier(Modifier::UNDERLINED),
+            primary: Style::new(),
+            secondary: bold,
+            muted: Style::new().add_modifier(Modifier::DIM),
+            marker: bold,
+            highlight: bold.add_modifier(Modifier::REVERSED),
+            axis: Style::new(),
+            ok: Style::new(),
+            warning: bold,
+            alert: bold.add_modifier(Modifier::REVERSED),
+            series: vec![Style::new()],
+        }
+    }
+
+    /// Style of the n-th series, repeating when there are more series than styles
+    pub fn series(&self, n: usize) -> Style {
+        self.series[n % self.series.len()]
+    }
+}
@@ -0,0 +1,223 @@
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Style, Stylize},
+    symbols::{border, Marker},
+    text::{Line, Span},
+    widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph},
+    Frame,
+};
+use tjaele_types::{DeviceState, HistorySample};
+
+use super::{TemperatureUnit, Theme, Trends};
+
+/// One series of a chart, as (age in seconds, value) points
+struct Series {
+    name: String,
+    style: Style,
+    graph_type: GraphType,
+    points: Vec<(f64, f64)>,
+    /// Whether min/avg/max are shown, there is room for a few only
+    annotated: bool,
+}
+
+/// Temperature, fans, power and clocks of the selected GPU over the trend window
+pub fn render_trend_charts(
+    frame: &mut Frame,
+    device: &DeviceState,
+    trends: &Trends,
+    theme: &Theme,
+    unit: TemperatureUnit,
+    area: Rect,
+) {
+    let samples = trends.window_samples(device.persistent.index);
+    let window = trends.window.duration().num_seconds() as f64;
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Length(1), Constraint::Fill(1), Constraint::Fill(1)])
+        .split(area);
+    let upper = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[1]);
+    let lower = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[2]);
+
+    let mut header = vec![
+        Span::from(format!(" Last {} ", trends.window)).bold(),
+        Span::from("(w to change window, c to hide charts)"),
+    ];
+    if let Some(error) = &trends.last_error {
+        header.push(Span::styled(format!("  {error}"), theme.warning));
+    }
+    frame.render_widget(Paragraph::new(Line::from(header)), rows[0]);
+
+    let temperature = Series {
+        name: "Core".to_string(),
+        style: theme.primary,
+        graph_type: GraphType::Line,
+        points: points(&samples, |sample| {
+            sample.temperature.map(|temp| unit.convert(f64::from(temp)))
+        }),
+        annotated: true,
+    };
+    render_chart(frame, upper[0], "Temperature", unit.symbol(), window, theme, vec![temperature]);
+
+    let fans = (0..device.persistent.num_fans)
+        .flat_map(|fan_idx| {
+            let style = theme.series(fan_idx);
+            let speed = Series {
+                name: format!("Fan {fan_idx} speed"),
+                style,
+                graph_type: GraphType::Line,
+                points: points(&samples, |sample| fan_value(sample.speeds.as_ref(), fan_idx)),
+                annotated: true,
+            };
+            let duty = Series {
+                name: format!("Fan {fan_idx} duty"),
+                style,
+                graph_type: GraphType::Scatter,
+                points: points(&samples, |sample| fan_value(sample.duties.as_ref(), fan_idx)),
+                annotated: false,
+            };
+            [speed, duty]
+        })
+        .collect();
+    render_chart(frame, upper[1], "Fans", "%", window, theme, fans);
+
+    let power = Series {
+        name: "Power".to_string(),
+        style: theme.primary,
+        graph_type: GraphType::Line,
+        points: points(&samples, |sample| sample.power),
+        annotated: true,
+    };
+    render_chart(frame, lower[0], "Power", "W", window, theme, vec![power]);
+
+    let clocks = vec![
+        Series {
+            name: "Graphics".to_string(),
+            style: theme.series(0),
+            graph_type: GraphType::Line,
+            points: points(&samples, |sample| {
+                sample.clocks.as_ref().map(|clocks| f64::from(clocks.graphics))
+            }),
+            annotated: true,
+        },
+        Series {
+            name: "Memory".to_string(),
+            style: theme.series(1),
+            graph_type: GraphType::Line,
+            points: points(&samples, |sample| {
+                sample.clocks.as_ref().map(|clocks| f64::from(clocks.memory))
+            }),
+            annotated: true,
+        },
+    ];
+    render_chart(frame, lower[1], "Clocks", "MHz", window, theme, clocks);
+}
+
+fn points(
+    samples: &[(f64, &HistorySample)],
+    value: impl Fn(&HistorySample) -> Option<f64>,
+) -> Vec<(f64, f64)> {
+    samples.iter().filter_map(|(age, sample)| Some((*age, value(sample)?))).collect()
+}
+
+fn fan_value(values: Option<&Vec<u32>>, fan_idx: usize) -> Option<f64> {
+    values?.get(fan_idx).copied().map(f64::from)
+}
+
+/// Chart with min/avg/max of every series below the title
+fn render_chart(
+    frame: &mut Frame,
+    area: Rect,
+    title: &str,
+    unit: &str,
+    window: f64,
+    theme: &Theme,
+    series: Vec<Series>,
+) {
+    let all_values = series.iter().flat_map(|series| series.points.iter().map(|(_, v)| *v));
+    let (min, max) = all_values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+
+    // some headroom, so flat lines are not drawn on the border
+    let (lower_bound, upper_bound) = if min.is_finite() {
+        let margin = ((max - min) * 0.1).max(1.0);
+        ((min - margin).max(0.0), max + margin)
+    } else {
+        (0.0, 100.0)
+    };
+
+    let mut block = Block::bordered()
+        .title(Line::from(title.to_string().bold()).left_aligned())
+        .border_set(border::PLAIN);
+    for series in series.iter().filter(|series| series.annotated) {
+        if let Some(stats) = stats(&series.points) {
+            let line = Line::from(format!(
+                "{} min {:.0} avg {:.1} max {:.0} {unit}",
+                series.name, stats.0, stats.1, stats.2
+            ))
+            .style(series.style)
+            .right_aligned();
+            block = block.title_bottom(line);
+        }
+    }
+
+    let datasets = series
+        .iter()
+        .map(|series| {
+            Dataset::default()
+                .graph_type(series.graph_type)
+                .marker(Marker::Braille)
+                .style(series.style)
+                .data(&series.points)
+        })
+        .collect::<Vec<_>>();
+
+    let chart = Chart::new(datasets)
+        .block(block)
+        .x_axis(
+            Axis::default()
+                .style(theme.axis)
+                .labels([format!("-{}", age_label(window)).bold(), "now".bold()])
+                .bounds([-window, 0.0]),
+        )
+        .y_axis(
+            Axis::default()
+                .style(theme.axis)
+                .labels([
+                    format!("{lower_bound:.0}").bold(),
+                    format!("{:.0}", (lower_bound + upper_bound) / 2.0).into(),
+                    format!("{upper_bound:.0}").bold(),
+                ])
+                .bounds([lower_bound, upper_bound]),
+        );
+
+    frame.render_widget(chart, area);
+}
+
+/// Minimum, average and maximum
+fn stats(points: &[(f64, f64)]) -> Option<(f64, f64, f64)> {
+    if points.is_empty() {
+        return None;
+    }
+
+    let (min, sum, max) =
+        points.iter().fold((f64::INFINITY, 0.0, f64::NEG_INFINITY), |(min, sum, max), (_, v)| {
+            (min.min(*v), sum + v, max.max(*v))
+        });
+    Some((min, sum / points.len() as f64, max))
+}
+
+fn age_label(seconds: f64) -> String {
+    if seconds >= 60.0 {
+        format!("{:.0}m", seconds / 60.0)
+    } else {
+        format!("{seconds:.0}s")
+    }
+}
@@ -1,38 +1,57 @@
 use pretty_bytes::converter::convert;
 use ratatui::{
     buffer::Buffer,
-    layout::{Constraint, Rect},
-    style::{Color, Style, Stylize},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::Stylize,
     symbols::{border, Marker},
-    text::{Line, Text},
+    text::{Line, Span, Text},
     widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph, Row, Table, Widget},
     Frame,
 };
+use tjaele_types::DeviceState;
 
-use super::MonitorData;
+use super::{MonitorData, TemperatureUnit, Theme};
+use crate::app::CurveEditor;
 
 pub(super) struct TimeBlock<'a> {
     pub(super) data: &'a MonitorData,
+    pub(super) device: &'a DeviceState,
+    pub(super) theme: &'a Theme,
 }
 
 pub(super) struct DeviceBlock<'a> {
-    pub(super) data: &'a MonitorData,
+    pub(super) device: &'a DeviceState,
+    pub(super) theme: &'a Theme,
 }
 
 pub(super) struct DriverBlock<'a> {
-    pub(super) data: &'a MonitorData,
+    pub(super) device: &'a DeviceState,
+    pub(super) theme: &'a Theme,
 }
 
 pub(super) struct TemperatureBlock<'a> {
-    pub(super) data: &'a MonitorData,
+    pub(super) device: &'a DeviceState,
+    pub(super) theme: &'a Theme,
+    pub(super) unit: TemperatureUnit,
 }
 
 pub(super) struct SpecsBlock<'a> {
+    pub(super) device: &'a DeviceState,
+    pub(super) theme: &'a Theme,
+    pub(super) unit: TemperatureUnit,
+}
+
+/// Everything important in two lines, for the compact layout
+pub(super) struct SummaryBlock<'a> {
     pub(super) data: &'a MonitorData,
+    pub(super) device: &'a DeviceState,
+    pub(super) theme: &'a Theme,
+    pub(super) unit: TemperatureUnit,
 }
 
 pub(super) struct ErrorBlock<'a> {
     pub(super) error: &'a anyhow::Error,
+    pub(super) theme: &'a Theme,
 }
 
 impl Widget for TimeBlock<'_> {
@@ -43,11 +62,77 @@ impl Widget for TimeBlock<'_> {
         let latency = self.data.latency.as_nanos() as f64 / 1_000_000.0;
 
         let text = Text::from(vec![
-            Line::from("System Time".to_string().yellow()),
-            Line::from(self.data.gpu_state.runtime.probe_time.to_rfc2822()),
+            Line::styled("System Time", self.theme.label),
+            Line::from(self.device.runtime.probe_time.to_rfc2822()),
             Line::from(""),
-            Line::from("GPU Probe Latency".to_string().yellow()),
+            Line::styled("GPU Probe Latency", self.theme.label),
             Line::from(format!("{latency:9.6} ms")),
+            Line::from(""),
+            Line::styled("Selected GPU", self.theme.label),
+            Line::from(format!(
+                "{} of {} (Tab to switch, c for charts)",
+                self.device.persistent.index + 1,
+                self.data.gpu_state.devices.len()
+            )),
+            Line::from(""),
+            Line::styled("Fan Control", self.theme.label),
+            Line::from(control_status(self.device, self.theme)),
+        ]);
+
+        Paragraph::new(text).block(block).render(area, buf);
+    }
+}
+
+fn control_status(device: &DeviceState, theme: &Theme) -> Span<'static> {
+    if let Some(degraded) = &device.degraded {
+        return Span::styled(
+            format!("DEGRADED ({} failures, retrying)", degraded.failures),
+            theme.alert,
+        );
+    }
+
+    if let Some(event) = device.safety_events.last().filter(|event| event.ended.is_none()) {
+        return Span::styled(format!("THERMAL EMERGENCY ({})", event.reaction), theme.alert);
+    }
+
+    if !device.fan_alarms.is_empty() {
+        return Span::styled(format!("{} mode, fan stalled", device.control_mode), theme.warning);
+    }
+
+    if device.interference_events > 0 {
+        return Span::from(format!(
+            "{} mode, {} external fan changes",
+            device.control_mode, device.interference_events
+        ));
+    }
+
+    Span::from(format!("{} mode", device.control_mode))
+}
+
+impl Widget for SummaryBlock<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = Line::from(vec!["Tjaele Monitor".bold(), " (Tab, c, e)".into()]);
+        let block = Block::bordered().title(title.left_aligned()).border_set(border::PLAIN);
+        let runtime = &self.device.runtime;
+
+        let text = Text::from(vec![
+            Line::from(vec![
+                Span::styled("GPU ", self.theme.label),
+                Span::from(format!(
+                    "{}/{} {}  ",
+                    self.device.persistent.index + 1,
+                    self.data.gpu_state.devices.len(),
+                    self.device.persistent.device_name
+                )),
+                Span::styled("Temp ", self.theme.label),
+                Span::from(format!("{}  ", self.unit.format(runtime.device_temperature))),
+                Span::styled("Power ", self.theme.label),
+                Span::from(format!("{:.0} W", runtime.power_usage)),
+            ]),
+            Line::from(vec![
+                Span::styled("Fan Control ", self.theme.label),
+                control_status(self.device, self.theme),
+            ]),
         ]);
 
         Paragraph::new(text).block(block).render(area, buf);
@@ -60,18 +145,17 @@ impl Widget for DeviceBlock<'_> {
         let block = Block::bordered().title(title.left_aligned()).border_set(border::PLAIN);
 
         let text = Text::from(vec![
-            Line::from("Device".to_string().yellow()),
+            Line::styled("Device", self.theme.label),
             Line::from(format!(
                 "{} ({} Architecture)",
-                self.data.gpu_state.persistent.device_name,
-                self.data.gpu_state.persistent.architecture
+                self.device.persistent.device_name, self.device.persistent.architecture
             )),
             Line::from(""),
-            Line::from("CUDA Cores".to_string().yellow()),
-            Line::from(self.data.gpu_state.persistent.num_cores.to_string()),
+            Line::styled("CUDA Cores", self.theme.label),
+            Line::from(self.device.persistent.num_cores.to_string()),
             Line::from(""),
-            Line::from("Fans Count".to_string().yellow()),
-            Line::from(self.data.gpu_state.persistent.num_fans.to_string()),
+            Line::styled("Fans Count", self.theme.label),
+            Line::from(self.device.persistent.num_fans.to_string()),
         ]);
 
         Paragraph::new(text).block(block).render(area, buf);
@@ -84,20 +168,20 @@ impl Widget for DriverBlock<'_> {
         let block = Block::bordered().title(title.left_aligned()).border_set(border::PLAIN);
 
         let text = Text::from(vec![
-            Line::from("Nvidia Driver Version".to_string().yellow()),
-            Line::from(self.data.gpu_state.persistent.sys_info.driver_version.to_string()),
+            Line::styled("Nvidia Driver Version", self.theme.label),
+            Line::from(self.device.persistent.sys_info.driver_version.to_string()),
             Line::from(""),
-            Line::from("CUDA Driver Version / Compute Capability".to_string().yellow()),
+            Line::styled("CUDA Driver Version / Compute Capability", self.theme.label),
             Line::from(format!(
                 "{}.{} / {}.{}",
-                self.data.gpu_state.persistent.sys_info.cuda_version.major,
-                self.data.gpu_state.persistent.sys_info.cuda_version.minor,
-                self.data.gpu_state.persistent.sys_info.cuda_capability.major,
-                self.data.gpu_state.persistent.sys_info.cuda_capability.minor
+                self.device.persistent.sys_info.cuda_version.major,
+                self.device.persistent.sys_info.cuda_version.minor,
+                self.device.persistent.sys_info.cuda_capability.major,
+                self.device.persistent.sys_info.cuda_capability.minor
             )),
             Line::from(""),
-            Line::from("NVML Version".to_string().yellow()),
-            Line::from(self.data.gpu_state.persistent.sys_info.nvml_version.to_string()),
+            Line::styled("NVML Version", self.theme.label),
+            Line::from(self.device.persistent.sys_info.nvml_version.to_string()),
         ]);
 
         Paragraph::new(text).block(block).render(area, buf);
@@ -109,21 +193,39 @@ impl Widget for TemperatureBlock<'_> {
         let title = Line::from("Temperatures".bold());
         let block = Block::bordered().title(title.left_aligned()).border_set(border::PLAIN);
 
-        let text = Text::from(vec![
-            Line::from("GPU".to_string().yellow()),
-            Line::from(format!("{} C", self.data.gpu_state.runtime.device_temperature)),
-        ]);
+        let unit = self.unit;
+        let mut lines = vec![
+            Line::styled("GPU", self.theme.label),
+            Line::from(unit.format(self.device.runtime.device_temperature)),
+        ];
+
+        if let Some(filtered_temperature) = self.device.runtime.filtered_temperature {
+            lines.push(Line::from(format!(
+                "{:.1} {} (filtered)",
+                unit.convert(filtered_temperature),
+                unit.symbol()
+            )));
+        }
+
+        if let Some(memory_temperature) = self.device.runtime.memory_temperature {
+            lines.extend([
+                Line::from(""),
+                Line::styled("Memory", self.theme.label),
+                Line::from(unit.format(memory_temperature)),
+            ]);
+        }
+
+        let text = Text::from(lines);
 
         Paragraph::new(text).block(block).render(area, buf);
     }
 }
 
-pub fn render_fans_table(frame: &mut Frame, data: &MonitorData, area: Rect) {
+pub fn render_fans_table(frame: &mut Frame, device: &DeviceState, theme: &Theme, area: Rect) {
     let title = Line::from("Fans".bold());
     let block = Block::bordered().title(title.left_aligned()).border_set(border::PLAIN);
 
-    let rows = data
-        .gpu_state
+    let rows = device
         .runtime
         .fan_states
         .iter()
@@ -132,16 +234,27 @@ pub fn render_fans_table(frame: &mut Frame, data: &MonitorData, area: Rect) {
                 fan_state.index.to_string(),
                 fan_state.speed.to_string(),
                 fan_state.duty.to_string(),
-                fan_state.control_policy.to_string(),
+                fan_state.target_duty.map_or_else(|| "-".to_string(), |duty| duty.to_string()),
+                if device.fan_alarms.iter().any(|alarm| alarm.fan == fan_state.index) {
+                    format!("{} (STALLED)", fan_state.control_policy)
+                } else {
+                    fan_state.control_policy.to_string()
+                },
             ])
         })
         .collect::<Vec<_>>();
 
-    let widths =
-        [Constraint::Length(5), Constraint::Length(9), Constraint::Length(8), Constraint::Fill(1)];
+    let widths = [
+        Constraint::Length(5),
+        Constraint::Length(9),
+        Constraint::Length(8),
+        Constraint::Length(10),
+        Constraint::Fill(1),
+    ];
     let table = Table::new(rows, widths)
         .header(
-            Row::new(vec!["Index", "Speed (%)", "Duty (%)", "Policy"]).style(Style::new().yellow()),
+            Row::new(vec!["Index", "Speed (%)", "Duty (%)", "Target (%)", "Policy"])
+                .style(theme.label),
         )
         .column_spacing(2)
         .block(block);
@@ -149,55 +262,52 @@ pub fn render_fans_table(frame: &mut Frame, data: &MonitorData, area: Rect) {
     frame.render_widget(table, area);
 }
 
-pub fn render_cooling_chart(frame: &mut Frame, data: &MonitorData, area: Rect) {
-    let title = Line::from("Fan Curve".bold());
+pub fn render_cooling_chart(
+    frame: &mut Frame,
+    device: &DeviceState,
+    theme: &Theme,
+    unit: TemperatureUnit,
+    area: Rect,
+) {
+    let title = Line::from(vec!["Fan Curve".bold(), " (e to edit)".into()]);
     let block = Block::bordered().title(title.left_aligned()).border_set(border::PLAIN);
 
-    let mut curve_data = data
-        .gpu_state
+    let (lower, upper) = temperature_bounds(device);
+
+    let mut curve_data = device
         .fan_curve
         .iter()
-        .map(|(t, d)| (f64::from(*t), f64::from(*d)))
+        .filter(|(t, _)| (lower..=upper).contains(&u32::from(*t)))
+        .map(|(t, d)| (unit.convert(f64::from(*t)), f64::from(*d)))
         .collect::<Vec<_>>();
     curve_data.sort_by(|(t1, _), (t2, _)| t2.total_cmp(t1));
 
-    let temp = f64::from(data.gpu_state.runtime.device_temperature);
+    let temp = unit.convert(f64::from(device.runtime.device_temperature));
 
-    let fans_data = data
-        .gpu_state
-        .runtime
-        .fan_states
-        .iter()
-        .map(|fs| (temp, f64::from(fs.speed)))
-        .collect::<Vec<_>>();
+    let fans_data =
+        device.runtime.fan_states.iter().map(|fs| (temp, f64::from(fs.speed))).collect::<Vec<_>>();
 
     let curve_dataset = Dataset::default()
         .graph_type(GraphType::Line)
         .name("Fan Curve")
         .marker(Marker::Braille)
-        .style(Style::default().fg(Color::Yellow))
+        .style(theme.primary)
         .data(&curve_data);
 
     let fans_dataset = Dataset::default()
         .graph_type(GraphType::Scatter)
         .name("Fans")
-        .style(Style::default().fg(Color::Blue))
+        .style(theme.secondary)
         .marker(Marker::Dot)
         .data(&fans_data);
 
     let chart = Chart::new(vec![curve_dataset, fans_dataset])
         .block(block)
-        .x_axis(
-            Axis::default()
-                .title("Temperature (C)")
-                .style(Style::default().fg(Color::Gray))
-                .labels(["10".bold(), "55".into(), "100".bold()])
-                .bounds([10.0, 100.0]),
-        )
+        .x_axis(temperature_axis(unit, lower, upper, theme))
         .y_axis(
             Axis::default()
                 .title("Fan Speed (%)")
-                .style(Style::default().fg(Color::Gray))
+                .style(theme.axis)
                 .labels(["0".bold(), "50".into(), "100".bold()])
                 .bounds([0.0, 100.0]),
         );
@@ -205,60 +315,224 @@ pub fn render_cooling_chart(frame: &mut Frame, data: &MonitorData, area: Rect) {
     frame.render_widget(chart, area);
 }
 
+/// Anchor points and the current temperature with some room around,
+/// rounded to tens of degrees Celsius
+fn temperature_bounds(device: &DeviceState) -> (u32, u32) {
+    let temps = device
+        .fan_curve_anchors
+        .iter()
+        .map(|(t, _)| u32::from(*t))
+        .chain([device.runtime.device_temperature]);
+    let (min, max) = temps.fold((u32::MAX, 0), |(min, max), t| (min.min(t), max.max(t)));
+
+    (min.saturating_sub(5) / 10 * 10, (max + 5).div_ceil(10) * 10)
+}
+
+/// Axis between temperatures in Celsius, labelled in `unit`
+fn temperature_axis(unit: TemperatureUnit, lower: u32, upper: u32, theme: &Theme) -> Axis<'static> {
+    let lower = unit.convert(f64::from(lower));
+    let upper = unit.convert(f64::from(upper));
+
+    Axis::default()
+        .title(format!("Temperature ({})", unit.symbol()))
+        .style(theme.axis)
+        .labels([
+            format!("{lower:.0}").bold(),
+            format!("{:.0}", (lower + upper) / 2.0).into(),
+            format!("{upper:.0}").bold(),
+        ])
+        .bounds([lower, upper])
+}
+
+/// Edited curve next to the current one, with anchor points and the duty
+/// the edited curve would set at the current temperature
+pub fn render_curve_editor(
+    frame: &mut Frame,
+    device: &DeviceState,
+    editor: &CurveEditor,
+    theme: &Theme,
+    unit: TemperatureUnit,
+    area: Rect,
+) {
+    let layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Percentage(70), Constraint::Percentage(30)])
+        .split(area);
+
+    let temp = device.runtime.device_temperature.min(u32::from(u8::MAX)) as u8;
+    let max_temp = editor.points.last().map_or(100, |point| point.0.saturating_add(10)).max(100);
+    let point = |(t, d): (u8, u8)| (unit.convert(f64::from(t)), f64::from(d));
+
+    let current_data = device
+        .fan_curve
+        .iter()
+        .filter(|(t, _)| *t <= max_temp)
+        .map(|(t, d)| point((*t, *d)))
+        .collect::<Vec<_>>();
+    let edited_data =
+        (0..=max_temp).filter_map(|t| Some(point((t, editor.duty_at(t)?)))).collect::<Vec<_>>();
+    let anchors_data = editor.points.iter().copied().map(point).collect::<Vec<_>>();
+    let selected_data =
+        editor.points.get(editor.selected).copied().map(point).into_iter().collect::<Vec<_>>();
+    let preview_data =
+        editor.duty_at(temp).map(|duty| point((temp, duty))).into_iter().collect::<Vec<_>>();
+
+    let datasets = vec![
+        Dataset::default()
+            .graph_type(GraphType::Scatter)
+            .name("Current")
+            .marker(Marker::Braille)
+            .style(theme.muted)
+            .data(&current_data),
+        Dataset::default()
+            .graph_type(GraphType::Line)
+            .name("Edited")
+            .marker(Marker::Braille)
+            .style(theme.primary)
+            .data(&edited_data),
+        Dataset::default()
+            .graph_type(GraphType::Scatter)
+            .name("Points")
+            .marker(Marker::Dot)
+            .style(theme.marker)
+            .data(&anchors_data),
+        Dataset::default()
+            .graph_type(GraphType::Scatter)
+            .name("Selected")
+            .marker(Marker::Block)
+            .style(theme.highlight)
+            .data(&selected_data),
+        Dataset::default()
+            .graph_type(GraphType::Scatter)
+            .name("Now")
+            .marker(Marker::Block)
+            .style(theme.secondary)
+            .data(&preview_data),
+    ];
+
+    let title = Line::from("Fan Curve Editor".bold());
+    let block = Block::bordered().title(title.left_aligned()).border_set(border::PLAIN);
+    let chart = Chart::new(datasets)
+        .block(block)
+        .x_axis(temperature_axis(unit, 0, u32::from(max_temp), theme))
+        .y_axis(
+            Axis::default()
+                .title("Fan Duty (%)")
+                .style(theme.axis)
+                .labels(["0".bold(), "50".into(), "100".bold()])
+                .bounds([0.0, 100.0]),
+        );
+    frame.render_widget(chart, layout[0]);
+
+    let mut lines = vec![Line::styled("Points", theme.label)];
+    lines.extend(editor.points.iter().enumerate().map(|(i, (t, d))| {
+        let line = format!("{:>3.0}{} {d:>3}%", unit.convert(f64::from(*t)), unit.symbol());
+        if i == editor.selected {
+            Line::styled(format!("> {line}"), theme.highlight)
+        } else {
+            Line::from(format!("  {line}"))
+        }
+    }));
+
+    lines.push(Line::from(""));
+    lines.push(Line::styled("Preview", theme.label));
+    let current_duty = device.fan_curve.iter().find(|(t, _)| *t == temp).map(|(_, d)| *d);
+    lines.push(Line::from(format!(
+        "At {}: {}% (now {}%)",
+        unit.format(temp),
+        editor.duty_at(temp).unwrap_or_default(),
+        current_duty.unwrap_or_default()
+    )));
+    lines.push(match editor.validate() {
+        Ok(()) => Line::styled("Curve is valid", theme.ok),
+        Err(err) => Line::styled(err.to_string(), theme.warning),
+    });
+
+    if let Some(message) = &editor.message {
+        lines.push(Line::from(""));
+        lines.push(match message {
+            Ok(message) => Line::styled(message.clone(), theme.ok),
+            Err(message) => Line::styled(message.clone(), theme.warning),
+        });
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::styled("Keys", theme.label));
+    lines.extend(
+        [
+            "Left/Right  select point",
+            "Up/Down     change duty",
+            "Shift+L/R   change temperature",
+            "a / d       add / delete point",
+            "Enter       apply (requires root)",
+            "s           save to file",
+            "Esc         leave editor",
+        ]
+        .map(Line::from),
+    );
+
+    let title = Line::from("Points".bold());
+    let block = Block::bordered().title(title.left_aligned()).border_set(border::PLAIN);
+    frame.render_widget(Paragraph::new(Text::from(lines)).block(block), layout[1]);
+}
+
 impl Widget for SpecsBlock<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let title = Line::from("GPU Specs".bold());
         let block = Block::bordered().title(title.left_aligned()).border_set(border::PLAIN);
 
         let text = Text::from(vec![
-            Line::from("Clock Speeds".to_string().yellow()),
+            Line::styled("Clock Speeds", self.theme.label),
             Line::from(format!(
                 "{} MHz (graphics), {} MHz (memory), {} MHz (video), {} MHz (SM)",
-                self.data.gpu_state.runtime.clock_speeds.graphics,
-                self.data.gpu_state.runtime.clock_speeds.memory,
-                self.data.gpu_state.runtime.clock_speeds.video,
-                self.data.gpu_state.runtime.clock_speeds.streaming_multiprocessor,
+                self.device.runtime.clock_speeds.graphics,
+                self.device.runtime.clock_speeds.memory,
+                self.device.runtime.clock_speeds.video,
+                self.device.runtime.clock_speeds.streaming_multiprocessor,
             )),
             Line::from(""),
-            Line::from("Memory".to_string().yellow()),
+            Line::styled("Memory", self.theme.label),
             Line::from(format!(
                 "{} (used), {} (total)",
-                convert(self.data.gpu_state.runtime.memory_info.used as _),
-                convert(self.data.gpu_state.runtime.memory_info.total as _),
+                convert(self.device.runtime.memory_info.used as _),
+                convert(self.device.runtime.memory_info.total as _),
             )),
             Line::from(""),
-            Line::from("Power Usage".to_string().yellow()),
-            Line::from(format!("{:.3} W", self.data.gpu_state.runtime.power_usage,)),
+            Line::styled("Power Usage", self.theme.label),
+            Line::from(match self.device.runtime.power_limit {
+                Some(power_limit) => {
+                    format!("{:.3} W (limit {power_limit:.0} W)", self.device.runtime.power_usage)
+                },
+                None => format!("{:.3} W", self.device.runtime.power_usage),
+            }),
             Line::from(""),
-            Line::from("PCIe Connection".to_string().yellow()),
+            Line::styled("PCIe Connection", self.theme.label),
             Line::from(format!(
                 "Current: {}x{} ({})",
-                self.data.gpu_state.runtime.current_pcie_link.gen,
-                self.data.gpu_state.runtime.current_pcie_link.width,
-                convert(self.data.gpu_state.runtime.current_pcie_link.speed as _).replace('B', "T")
-                    + "/s",
+                self.device.runtime.current_pcie_link.gen,
+                self.device.runtime.current_pcie_link.width,
+                convert(self.device.runtime.current_pcie_link.speed as _).replace('B', "T") + "/s",
             )),
             Line::from(format!(
                 "Maximum: {}x{} ({})",
-                self.data.gpu_state.persistent.max_pcie_link.gen,
-                self.data.gpu_state.persistent.max_pcie_link.width,
-                convert(self.data.gpu_state.persistent.max_pcie_link.speed as _).replace('B', "T")
-                    + "/s",
+                self.device.persistent.max_pcie_link.gen,
+                self.device.persistent.max_pcie_link.width,
+                convert(self.device.persistent.max_pcie_link.speed as _).replace('B', "T") + "/s",
             )),
             Line::from(""),
-            Line::from("Temperature Thresholds".to_string().yellow()),
+            Line::styled("Temperature Thresholds", self.theme.label),
             Line::from(format!(
-                "{} C (shutdown), {} C (slowdown), {} C (gpumax)",
-                self.data.gpu_state.persistent.temp_thresholds.shutdown,
-                self.data.gpu_state.persistent.temp_thresholds.slowdown,
-                self.data.gpu_state.persistent.temp_thresholds.gpumax
+                "{} (shutdown), {} (slowdown), {} (gpumax)",
+                self.unit.format(self.device.persistent.temp_thresholds.shutdown),
+                self.unit.format(self.device.persistent.temp_thresholds.slowdown),
+                self.unit.format(self.device.persistent.temp_thresholds.gpumax)
             )),
             Line::from(""),
-            Line::from("Fan Speed Thresholds".to_string().yellow()),
+            Line::styled("Fan Speed Thresholds", self.theme.label),
             Line::from(format!(
                 "{}% (min), {}% (max)",
-                self.data.gpu_state.persistent.minmax_fan_speeds.min,
-                self.data.gpu_state.persistent.minmax_fan_speeds.max,
+                self.device.persistent.minmax_fan_speeds.min,
+                self.device.persistent.minmax_fan_speeds.max,
             )),
         ]);
 
@@ -271,10 +545,10 @@ impl Widget for ErrorBlock<'_> {
         let title = Line::from("Tjaele Monitor Error".bold());
         let block = Block::bordered().title(title.centered()).border_set(border::THICK);
 
-        let mut lines =
-            vec![Line::from(vec!["Tjaele Monitor failed to acquire GPU data with error chain: "
-                .to_string()
-                .yellow()])];
+        let mut lines = vec![Line::styled(
+            "Tjaele Monitor failed to acquire GPU data with error chain: ",
+            self.theme.label,
+        )];
 
         for (i, e) in self.error.chain().enumerate() {
             lines.push(Line::from(vec![format!("[{i}]: {e}\n").into()]));
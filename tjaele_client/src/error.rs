@@ -0,0 +1,52 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tjaele_types::api::{Compatibility, API_VERSION};
+
+/// In the chain of errors when the daemon could not be talked to at all,
+/// as opposed to the daemon rejecting the request with `ApiError`
+#[derive(Debug, Clone)]
+pub enum DaemonUnavailable {
+    /// Socket is missing or refuses connections
+    Unreachable(PathBuf),
+    TimedOut(Duration),
+    /// Daemon speaks another API version
+    Incompatible {
+        compatibility: Compatibility,
+        api_version: Option<u32>,
+    },
+}
+
+impl Display for DaemonUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DaemonUnavailable::Unreachable(socket_path) => write!(
+                f,
+                "Failed to connect to tjaeled at {}, is control unit running?",
+                socket_path.display()
+            ),
+            DaemonUnavailable::TimedOut(timeout) => {
+                write!(f, "tjaeled did not respond within {timeout:?}")
+            },
+            DaemonUnavailable::Incompatible { compatibility, api_version } => {
+                let api_version =
+                    api_version.map_or("unknown".to_string(), |version| version.to_string());
+                match compatibility {
+                    Compatibility::DaemonTooNew => write!(
+                        f,
+                        "tjaeled is too new (API version {api_version}, tjaele needs \
+                         {API_VERSION}), please update tjaele"
+                    ),
+                    _ => write!(
+                        f,
+                        "tjaeled is too old (API version {api_version}, tjaele needs \
+                         {API_VERSION}), please update and restart tjaeled"
+                    ),
+                }
+            },
+        }
+    }
+}
+
+impl std::error::Error for DaemonUnavailable {}
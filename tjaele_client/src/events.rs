@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+use futures::Stream;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::client::conn::http1::SendRequest;
+use tjaele_types::DaemonEvent;
+
+/// Parses server-sent events of `GET /events` as they arrive
+pub(super) fn event_stream(
+    sender: SendRequest<Full<Bytes>>,
+    body: Incoming,
+) -> impl Stream<Item = Result<DaemonEvent>> {
+    // sender is kept, so the connection lives as long as the stream
+    let state = (sender, body, Vec::new());
+
+    futures::stream::unfold(state, |(sender, mut body, mut buffer)| async move {
+        loop {
+            if let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+                let message = buffer.drain(..end + 2).collect::<Vec<_>>();
+                return Some((parse_event(&message), (sender, body, buffer)));
+            }
+
+            match body.frame().await? {
+                Ok(frame) => {
+                    if let Ok(data) = frame.into_data() {
+                        buffer.extend_from_slice(&data);
+                    }
+                },
+                Err(err) => {
+                    let err = anyhow::Error::new(err).context("Event stream failed");
+                    return Some((Err(err), (sender, body, buffer)));
+                },
+            }
+        }
+    })
+}
+
+/// Only the data lines matter, event names are repeated in the JSON
+fn parse_event(message: &[u8]) -> Result<DaemonEvent> {
+    let message = std::str::from_utf8(message).context("Event is not UTF-8")?;
+    let data = message
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(str::trim_start)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    serde_json::from_str(&data).context("Failed to parse event of tjaeled")
+}
@@ -0,0 +1,297 @@
+//! Client of the `tjaeled` Unix socket, for the TUI and anything else built on top of the daemon.
+//!
+//! Connections are kept open and reused between requests, except for
+//! the event stream, which holds its own connection for as long as it lives.
+
+mod error;
+mod events;
+#[cfg(test)]
+mod tests;
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use futures::Stream;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::client::conn::http1::{self, SendRequest};
+use hyper::{HeaderMap, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::de::DeserializeOwned;
+use tjaele_types::api::{
+    paths, ApiError, Compatibility, HistoryQuery, VersionInfo, API_VERSION_HEADER,
+};
+use tjaele_types::{
+    DaemonEvent, DutyOverrideRequest, FanCurveUpdate, GpuHistory, GpuState, HistoryField, SOCKET,
+};
+use tokio::net::UnixStream;
+use tokio::time::timeout;
+
+pub use error::DaemonUnavailable;
+pub use tjaele_types;
+
+/// Where and how patiently the client talks to the daemon
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub socket_path: PathBuf,
+    pub connect_timeout: Duration,
+    /// Covers sending the request and reading the whole response
+    pub request_timeout: Duration,
+    /// Idle connections kept for reuse
+    pub max_idle_connections: usize,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            socket_path: PathBuf::from(SOCKET),
+            connect_timeout: Duration::from_secs(2),
+            request_timeout: Duration::from_secs(10),
+            max_idle_connections: 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TjaeleClient {
+    config: ClientConfig,
+    idle_connections: Mutex<Vec<SendRequest<Full<Bytes>>>>,
+}
+
+impl Default for TjaeleClient {
+    fn default() -> Self {
+        TjaeleClient::new(ClientConfig::default())
+    }
+}
+
+impl TjaeleClient {
+    pub fn new(config: ClientConfig) -> Self {
+        TjaeleClient { config, idle_connections: Mutex::new(Vec::new()) }
+    }
+
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// Checks that the daemon speaks the same API version
+    pub async fn version(&self) -> Result<VersionInfo> {
+        self.get_json(paths::VERSION).await
+    }
+
+    pub async fn gpu_state(&self) -> Result<GpuState> {
+        self.get_json(paths::GPU_STATE).await
+    }
+
+    /// Samples recorded after `since`, all fields when `fields` is empty
+    pub async fn history(
+        &self,
+        since: Option<DateTime<Local>>,
+        fields: &[HistoryField],
+    ) -> Result<GpuHistory> {
+        let fields = (!fields.is_empty())
+            .then(|| fields.iter().map(ToString::to_string).collect::<Vec<_>>().join(","));
+        let query = serde_urlencoded::to_string(HistoryQuery { since, fields })
+            .context("Failed to encode history query")?;
+
+        self.get_json(&format!("{}?{query}", paths::HISTORY)).await
+    }
+
+    /// Metrics in OpenMetrics text format
+    pub async fn metrics(&self) -> Result<String> {
+        let body = self.request(Method::GET, paths::METRICS, None).await?;
+        String::from_utf8(body.to_vec()).context("Metrics are not UTF-8")
+    }
+
+    pub async fn set_fan_curve(&self, update: &FanCurveUpdate) -> Result<()> {
+        let body = serde_json::to_string(update)?;
+        self.request(Method::PUT, paths::FAN_CURVE, Some(body)).await?;
+
+        Ok(())
+    }
+
+    /// Replaces the whole control config with `config` in TOML
+    pub async fn set_config(&self, config: &str) -> Result<()> {
+        self.request(Method::PUT, paths::CONFIG, Some(config.to_string())).await?;
+
+        Ok(())
+    }
+
+    pub async fn set_duty_override(&self, request: &DutyOverrideRequest) -> Result<()> {
+        let body = serde_json::to_string(request)?;
+        self.request(Method::PUT, paths::OVERRIDE, Some(body)).await?;
+
+        Ok(())
+    }
+
+    pub async fn clear_duty_overrides(&self) -> Result<()> {
+        self.request(Method::DELETE, paths::OVERRIDE, None).await?;
+
+        Ok(())
+    }
+
+    /// Acknowledges fan alarms, returning stalled fans to curve control
+    pub async fn clear_fan_alarms(&self) -> Result<()> {
+        self.request(Method::DELETE, paths::ALARMS, None).await?;
+
+        Ok(())
+    }
+
+    /// Events as they happen, the stream ends when the daemon stops
+    pub async fn events(&self) -> Result<impl Stream<Item = Result<DaemonEvent>>> {
+        let mut sender = self.connect().await?;
+        let response = timeout(
+            self.config.request_timeout,
+            sender.send_request(new_request(Method::GET, paths::EVENTS, Bytes::new())?),
+        )
+        .await
+        .map_err(|_| self.timed_out())??;
+
+        let response = check_response(response).await?;
+        Ok(events::event_stream(sender, response.into_body()))
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let body = self.request(Method::GET, path, None).await?;
+        serde_json::from_slice(&body).context("Failed to parse response of tjaeled")
+    }
+
+    async fn request(&self, method: Method, path: &str, body: Option<String>) -> Result<Bytes> {
+        let body = Bytes::from(body.unwrap_or_default());
+
+        timeout(self.config.request_timeout, async {
+            let (sender, response) = self.send(method, path, body).await?;
+            let (parts, body) = response.into_parts();
+
+            // only connections with the response read completely can be reused,
+            // so the body is read even when the request failed
+            let body = body.collect().await?.to_bytes();
+            self.release(sender);
+
+            check_api_version(&parts.headers)?;
+            if !parts.status.is_success() {
+                return Err(response_error(parts.status, &body));
+            }
+
+            Ok(body)
+        })
+        .await
+        .map_err(|_| self.timed_out())?
+    }
+
+    /// Sends the request over an idle connection if any is still open, otherwise over a new one
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Bytes,
+    ) -> Result<(SendRequest<Full<Bytes>>, Response<Incoming>)> {
+        if let Some(mut sender) = self.idle_connection().await? {
+            // daemon can close the connection after it was found ready, all requests
+            // are idempotent, so they are sent once more over a new connection
+            if let Ok(response) =
+                sender.send_request(new_request(method.clone(), path, body.clone())?).await
+            {
+                return Ok((sender, response));
+            }
+        }
+
+        let mut sender = self.connect().await?;
+        let response = sender.send_request(new_request(method, path, body)?).await?;
+
+        Ok((sender, response))
+    }
+
+    /// Idle connection which is still open, if any
+    async fn idle_connection(&self) -> Result<Option<SendRequest<Full<Bytes>>>> {
+        loop {
+            let idle = self.idle_connections.lock().map_err(|_| anyhow!("Poisoned lock"))?.pop();
+            let Some(mut sender) = idle else {
+                return Ok(None);
+            };
+
+            if sender.ready().await.is_ok() {
+                return Ok(Some(sender));
+            }
+        }
+    }
+
+    fn release(&self, sender: SendRequest<Full<Bytes>>) {
+        if sender.is_closed() {
+            return;
+        }
+
+        if let Ok(mut idle) = self.idle_connections.lock() {
+            if idle.len() < self.config.max_idle_connections {
+                idle.push(sender);
+            }
+        }
+    }
+
+    async fn connect(&self) -> Result<SendRequest<Full<Bytes>>> {
+        let socket_path = &self.config.socket_path;
+        let stream = timeout(self.config.connect_timeout, UnixStream::connect(socket_path))
+            .await
+            .map_err(|_| anyhow!("Connecting took longer than {:?}", self.config.connect_timeout))
+            .and_then(|stream| stream.map_err(anyhow::Error::from))
+            .context(DaemonUnavailable::Unreachable(socket_path.clone()))?;
+
+        let (sender, conn) = http1::handshake(TokioIo::new(stream)).await?;
+
+        // drives the connection until it is closed by either side,
+        // errors surface in requests sent over it
+        tokio::task::spawn(async move {
+            let _ = conn.await;
+        });
+
+        Ok(sender)
+    }
+
+    fn timed_out(&self) -> anyhow::Error {
+        DaemonUnavailable::TimedOut(self.config.request_timeout).into()
+    }
+}
+
+fn new_request(method: Method, path: &str, body: Bytes) -> Result<Request<Full<Bytes>>> {
+    let request = Request::builder().method(method).uri(path).body(Full::new(body))?;
+
+    Ok(request)
+}
+
+/// Turns incompatible daemons and failed requests into errors
+async fn check_response(response: Response<Incoming>) -> Result<Response<Incoming>> {
+    check_api_version(response.headers())?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.into_body().collect().await?.to_bytes();
+    Err(response_error(status, &body))
+}
+
+fn response_error(status: StatusCode, body: &[u8]) -> anyhow::Error {
+    // kept as the source, so callers can tell what kind of error it was
+    match serde_json::from_slice::<ApiError>(body) {
+        Ok(api_error) => {
+            anyhow::Error::new(api_error).context(format!("tjaeled responded with {status}"))
+        },
+        Err(_) => {
+            anyhow!("tjaeled responded with {status}: {}", String::from_utf8_lossy(body).trim_end())
+        },
+    }
+}
+
+fn check_api_version(headers: &HeaderMap) -> Result<()> {
+    let api_version = headers
+        .get(API_VERSION_HEADER)
+        .and_then(|version| version.to_str().ok()?.parse::<u32>().ok());
+
+    match Compatibility::of(api_version) {
+        Compatibility::Compatible => Ok(()),
+        compatibility => Err(DaemonUnavailable::Incompatible { compatibility, api_version }.into()),
+    }
+}
@@ -0,0 +1,108 @@
+//! Connection reuse against a daemon which answers with fixed responses
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use tjaele_types::api::{API_VERSION, API_VERSION_HEADER};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{UnixListener, UnixStream},
+};
+
+use super::{ClientConfig, TjaeleClient};
+
+/// Answers every request with `status`, but drops each connection without an answer
+/// after `answers_per_connection` requests, as a daemon closing idle connections would
+struct FakeDaemon {
+    socket_path: PathBuf,
+    connections: Arc<AtomicUsize>,
+}
+
+impl FakeDaemon {
+    fn start(name: &str, status: &'static str, answers_per_connection: usize) -> Self {
+        let socket_path =
+            std::env::temp_dir().join(format!("tjaele-client-{}-{name}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    for _ in 0..answers_per_connection {
+                        if !read_request(&mut stream).await {
+                            return;
+                        }
+                        let response = format!(
+                            "HTTP/1.1 {status}\r\n{API_VERSION_HEADER}: {API_VERSION}\r\n\
+                             content-length: 2\r\n\r\nok"
+                        );
+                        stream.write_all(response.as_bytes()).await.unwrap();
+                    }
+                    read_request(&mut stream).await;
+                });
+            }
+        });
+
+        FakeDaemon { socket_path, connections }
+    }
+
+    fn client(&self) -> TjaeleClient {
+        TjaeleClient::new(ClientConfig {
+            socket_path: self.socket_path.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for FakeDaemon {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.socket_path);
+    }
+}
+
+/// Reads the request head, requests sent by the client have no body
+async fn read_request(stream: &mut UnixStream) -> bool {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return false,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    true
+}
+
+#[tokio::test]
+async fn reuses_connection_after_error_response() {
+    let daemon = FakeDaemon::start("error", "404 Not Found", usize::MAX);
+    let client = daemon.client();
+
+    assert!(client.metrics().await.is_err());
+    assert!(client.metrics().await.is_err());
+    assert_eq!(daemon.connections(), 1);
+}
+
+#[tokio::test]
+async fn retries_on_new_connection_when_idle_one_was_closed() {
+    let daemon = FakeDaemon::start("closed", "200 OK", 1);
+    let client = daemon.client();
+
+    assert_eq!(client.metrics().await.unwrap(), "ok");
+    assert_eq!(client.metrics().await.unwrap(), "ok");
+    assert_eq!(daemon.connections(), 2);
+}
@@ -0,0 +1,100 @@
+//! Contract between tjaeled and its clients on the Unix socket.
+//!
+//! Bodies of requests and responses are JSON of the types in this crate,
+//! except for the TOML config of `PUT /config` and the text of `/metrics` and `/events`.
+
+use std::fmt::Display;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// Incremented whenever requests or responses change in a way
+/// which older clients (or daemons) cannot handle
+pub const API_VERSION: u32 = 1;
+
+/// Carries `API_VERSION` of the daemon on every response
+pub const API_VERSION_HEADER: &str = "tjaele-api-version";
+
+/// Endpoints of the daemon
+pub mod paths {
+    /// `GET` returns `VersionInfo`
+    pub const VERSION: &str = "/version";
+    /// `GET` returns `GpuState`
+    pub const GPU_STATE: &str = "/gpustate";
+    /// `GET` returns `GpuHistory`
+    pub const HISTORY: &str = "/history";
+    /// `GET` returns metrics in OpenMetrics text format
+    pub const METRICS: &str = "/metrics";
+    /// `GET` streams `DaemonEvent`s as server-sent events
+    pub const EVENTS: &str = "/events";
+    /// `PUT` takes `FanCurveUpdate`
+    pub const FAN_CURVE: &str = "/fancurve";
+    /// `PUT` takes the whole TOML config
+    pub const CONFIG: &str = "/config";
+    /// `PUT` takes `DutyOverrideRequest`, `DELETE` ends all overrides
+    pub const OVERRIDE: &str = "/override";
+    /// `DELETE` acknowledges all fan alarms
+    pub const ALARMS: &str = "/alarms";
+}
+
+/// Response of `GET /version`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub api_version: u32,
+    pub daemon_version: String,
+}
+
+/// Query of `GET /history`, eg. `?since=2025-01-01T12:00:00Z&fields=temperature,duty`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryQuery {
+    pub since: Option<DateTime<Local>>,
+    /// Comma separated `HistoryField`s, all of them when missing
+    pub fields: Option<String>,
+}
+
+/// Whether a client built with this crate can talk to the daemon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compatibility {
+    Compatible,
+    DaemonTooOld,
+    DaemonTooNew,
+}
+
+impl Compatibility {
+    /// Daemons which predate versioning do not report any version
+    pub fn of(daemon_api_version: Option<u32>) -> Self {
+        match daemon_api_version {
+            Some(API_VERSION) => Compatibility::Compatible,
+            Some(version) if version > API_VERSION => Compatibility::DaemonTooNew,
+            Some(_) | None => Compatibility::DaemonTooOld,
+        }
+    }
+}
+
+/// Body of every failed request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiError {
+    pub kind: ApiErrorKind,
+    /// Outermost error first
+    pub chain: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, derive_more::Display)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiErrorKind {
+    /// Request is malformed or was rejected, eg. invalid config
+    InvalidRequest,
+    /// Only root can modify fan control
+    Forbidden,
+    NotFound,
+    /// Daemon failed to handle a valid request
+    Internal,
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.chain.join(": "))
+    }
+}
+
+impl std::error::Error for ApiError {}
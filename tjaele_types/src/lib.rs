@@ -1,18 +1,262 @@
+pub mod api;
 mod impls;
 #[cfg(feature = "nvml_types")]
 mod nvml_integration;
 
+use std::time::Duration;
+
 use chrono::{DateTime, Local};
 use derive_more::derive::Display;
 use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
 
 pub const SOCKET: &str = "/var/run/tjaele/tjaeled.sock";
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GpuState {
+    pub devices: Vec<DeviceState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceState {
     pub runtime: RuntimeGpuParams,
     pub persistent: PersistentGpuParams,
     pub fan_curve: Vec<(u8, u8)>,
+    /// Points `fan_curve` is interpolated from, sorted by temperature
+    #[serde(default)]
+    pub fan_curve_anchors: Vec<(u8, u8)>,
+    pub control_mode: ControlMode,
+    pub duty_override: Option<ActiveDutyOverride>,
+    /// Fans which could not keep up with their duty
+    pub fan_alarms: Vec<FanAlarm>,
+    /// Recent thermal emergencies, newest last
+    pub safety_events: Vec<SafetyEvent>,
+    /// Set while the GPU cannot be controlled, runtime params are the last ones read then
+    pub degraded: Option<DegradedState>,
+    /// Times fans controlled by tjaeled were found changed by something else
+    pub interference_events: u32,
+    /// Duty writes which changed the duty of a fan, since the daemon started
+    pub duty_changes: u64,
+    /// Failed control steps, since the daemon started
+    pub control_errors: u64,
+}
+
+/// Pushed to clients subscribed to `GET /events`, the event name
+/// is also the name of the server-sent event
+#[derive(Debug, Clone, Serialize, Deserialize, Display)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    /// State of one GPU after each of its control steps
+    #[display("state")]
+    State(Box<DeviceState>),
+    /// Duty written to a fan differs from the previous one
+    #[display("duty_changed")]
+    DutyChanged { device: usize, fan: usize, duty: u8 },
+    /// Control config was replaced or reloaded
+    #[display("config_changed")]
+    ConfigChanged,
+    /// Thermal emergency started, or ended if `ended` is set
+    #[display("thermal_emergency")]
+    ThermalEmergency { device: usize, event: SafetyEvent },
+    #[display("fan_stalled")]
+    FanStalled { device: usize, alarm: FanAlarm },
+    /// Stalled fan keeps up with its duty again and its alarm was cleared
+    #[display("fan_recovered")]
+    FanRecovered { device: usize, fan: usize },
+    /// Control step failed, the daemon retries if `retrying` is set
+    #[display("control_error")]
+    ControlError { device: usize, error: String, retrying: bool },
+    #[display("control_recovered")]
+    ControlRecovered { device: usize },
+}
+
+/// Telemetry recorded by the daemon, oldest samples first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GpuHistory {
+    pub devices: Vec<DeviceHistory>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceHistory {
+    pub index: usize,
+    pub samples: Vec<HistorySample>,
+}
+
+/// Taken on every control step, fields which were not requested are left out
+#[serde_with::skip_serializing_none]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySample {
+    pub time: DateTime<Local>,
+    /// Core temperature in Celsius
+    #[serde(default)]
+    pub temperature: Option<u32>,
+    /// Duty of each fan
+    #[serde(default)]
+    pub duties: Option<Vec<u32>>,
+    /// Speed of each fan
+    #[serde(default)]
+    pub speeds: Option<Vec<u32>>,
+    /// Watts
+    #[serde(default)]
+    pub power: Option<f64>,
+    #[serde(default)]
+    pub clocks: Option<ClockSpeeds>,
+}
+
+/// Group of values in history samples, which can be requested separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryField {
+    #[display("temperature")]
+    Temperature,
+    #[display("duty")]
+    #[serde(rename = "duty")]
+    Duties,
+    #[display("speed")]
+    #[serde(rename = "speed")]
+    Speeds,
+    #[display("power")]
+    Power,
+    #[display("clocks")]
+    Clocks,
+}
+
+/// Body of the fan curve replacement request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FanCurveUpdate {
+    /// GPU UUID or PCI bus id, default curve is replaced when not provided
+    pub device: Option<String>,
+    /// Anchor points as [GPU_TEMP(C), FAN_DUTY(%)]
+    pub fan_curve: Vec<(u8, u8)>,
+}
+
+/// Why anchor points cannot make a fan curve
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum InvalidFanCurve {
+    #[display("Fan duty cannot be higher than 100%")]
+    DutyTooHigh,
+    #[display("Fan curve must have at least 3 points")]
+    TooFewPoints,
+    #[display("Fan duty must not decrease with temperature")]
+    DecreasingDuty,
+}
+
+impl std::error::Error for InvalidFanCurve {}
+
+/// Checks anchor points sorted by temperature, tjaeled and the curve editor
+/// apply the same rules
+pub fn validate_fan_curve(anchor_points: &[(u8, u8)]) -> Result<(), InvalidFanCurve> {
+    if anchor_points.iter().any(|&(_, duty)| duty > 100) {
+        return Err(InvalidFanCurve::DutyTooHigh);
+    }
+    if anchor_points.len() < 3 {
+        return Err(InvalidFanCurve::TooFewPoints);
+    }
+    if anchor_points.windows(2).any(|pair| pair[0].1 > pair[1].1) {
+        return Err(InvalidFanCurve::DecreasingDuty);
+    }
+
+    Ok(())
+}
+
+/// Body of the request pinning fans at a fixed duty
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DutyOverrideRequest {
+    /// GPU UUID or PCI bus id, all GPUs are affected when not provided
+    pub device: Option<String>,
+    pub duty: u8,
+    /// Indices of overridden fans, all fans are affected when not provided
+    pub fans: Option<Vec<usize>>,
+    /// Curve control is restored after this time, override is held indefinitely when not provided
+    #[serde_as(as = "Option<serde_with::DurationSecondsWithFrac<f64>>")]
+    pub duration: Option<Duration>,
+}
+
+/// Fixed duty currently held instead of the fan curve
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveDutyOverride {
+    pub duty: u8,
+    pub fans: Vec<usize>,
+    pub expires: Option<DateTime<Local>>,
+}
+
+/// Period when fans were taken over because the GPU got too close to its temperature limits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafetyEvent {
+    pub started: DateTime<Local>,
+    /// None while the emergency lasts
+    pub ended: Option<DateTime<Local>>,
+    /// Temperature at which the emergency started
+    pub threshold: u32,
+    pub max_temperature: u32,
+    pub reaction: EmergencyReaction,
+}
+
+/// Consecutive failures of fan control which the daemon keeps retrying
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DegradedState {
+    pub since: DateTime<Local>,
+    pub failures: u32,
+    pub last_error: String,
+}
+
+/// How fans are handled during thermal emergency
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum EmergencyReaction {
+    /// All fans run at 100%
+    #[default]
+    MaxDuty,
+    /// All fans go back to automatic policy
+    Automatic,
+}
+
+/// Raised when fan speed stays too far below its duty
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FanAlarm {
+    pub fan: usize,
+    pub raised: DateTime<Local>,
+    /// Duty and speed when the alarm was raised
+    pub duty: u8,
+    pub speed: u32,
+    pub reaction: StallReaction,
+}
+
+/// What the daemon does when a fan stalls, alarms are always logged and reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum StallReaction {
+    /// Nothing besides the alarm
+    #[default]
+    Alarm,
+    /// All fans of the GPU run at 100%
+    MaxOtherFans,
+    /// All fans of the GPU go back to automatic policy
+    Automatic,
+}
+
+/// Strategy used for computing fan duty
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlMode {
+    /// Duty looked up from the fan curves
+    #[default]
+    Curve,
+    /// Duty adjusted by a PID controller to hold the setpoint
+    Pid,
+}
+
+/// Measurement which fan duty can be controlled by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlInput {
+    /// Celsius
+    CoreTemperature,
+    /// Celsius
+    MemoryTemperature,
+    /// Percent of the enforced power limit
+    Power,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,8 +265,14 @@ pub struct RuntimeGpuParams {
     pub current_pcie_link: PCIeLink,
     pub memory_info: GpuMemStats,
     pub power_usage: f64,
-    // there's only one temperature sensor variant
+    /// Power limit currently enforced by the driver (W), not reported by all GPUs
+    pub power_limit: Option<f64>,
+    /// Core temperature
     pub device_temperature: u32,
+    /// Core temperature after the input filter, none before the first control step
+    pub filtered_temperature: Option<f64>,
+    /// Not reported by all GPUs (mostly those with GDDR6X or HBM memory)
+    pub memory_temperature: Option<u32>,
     pub fan_states: Vec<FanState>,
     pub clock_speeds: ClockSpeeds,
 }
@@ -30,6 +280,10 @@ pub struct RuntimeGpuParams {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PersistentGpuParams {
     pub sys_info: SysInfo,
+    /// NVML device index
+    pub index: usize,
+    pub uuid: String,
+    pub pci_bus_id: String,
     pub device_name: String,
     pub architecture: GpuArchitecture,
     pub num_cores: u32,
@@ -82,6 +336,8 @@ pub struct FanState {
     pub speed: u32,
     /// Speed fan is set to
     pub duty: u32,
+    /// Duty tjaeled last requested for the fan (from its curve or an override)
+    pub target_duty: Option<u8>,
     pub control_policy: FanControlPolicy,
 }
 
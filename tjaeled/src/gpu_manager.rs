@@ -1,121 +1,449 @@
-use std::{ffi::OsStr, fmt::Debug, path::Path, time::Duration};
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard,
+    },
+    time::{Instant, SystemTime},
+};
 
-mod device_probe;
+mod backend;
+mod control_config;
+mod controller;
+mod duty_override;
+mod duty_ramp;
+mod events;
 mod fan_curve;
-mod intermediate_bindings;
+mod fan_stall;
+mod history;
+mod input_filter;
+mod recorder;
+mod recovery;
+mod resume;
+#[cfg(test)]
+mod tests;
+mod thermal_emergency;
+mod verification;
 
-use anyhow::{ensure, Result};
-use intermediate_bindings::AdditionalNvmlFunctionality;
-use nvml_wrapper::{Device, Nvml};
-use ouroboros::self_referencing;
+use anyhow::{ensure, Context, Result};
+pub use backend::{GpuBackend, NvmlHandle, SimulatedBackend};
+pub use control_config::{DeviceControlConfig, TjaeleControlConfig};
+pub use controller::Controller;
+use duty_override::DutyOverride;
+use duty_ramp::DutyRamp;
+use fan_stall::FanStall;
+use input_filter::InputFilter;
+use recorder::TelemetryRecorder;
 use rustc_hash::FxHashMap;
-use serde::Deserialize;
-use serde_with::serde_as;
-use tjaele_types::{GpuState, PersistentGpuParams};
-use tracing::info;
+use tjaele_types::{
+    ControlInput, ControlMode, DaemonEvent, DegradedState, DeviceState, FanCurveUpdate, GpuState,
+    HistorySample, PersistentGpuParams, RuntimeGpuParams, SafetyEvent, StallReaction,
+};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
 
 #[derive(Debug)]
 pub struct GpuManager {
-    nvml_handle: NvmlHandle,
-    persistent_params: PersistentGpuParams,
-    pub control_config: TjaeleControlConfig,
+    backend: Box<dyn GpuBackend>,
+    config_path: PathBuf,
+    /// Indexed the same way as devices in `backend`
+    persistent_params: Vec<PersistentGpuParams>,
+    /// Always replaced as a whole, so readers never see partially applied changes
+    control_config: RwLock<TjaeleControlConfig>,
+    /// Indexed the same way as devices in `backend`
+    control_states: Mutex<Vec<DeviceControlState>>,
+    /// Incremented every time control config is replaced or an override ends
+    control_generation: AtomicU64,
+    /// Telemetry samples of each GPU, oldest first
+    history: Mutex<Vec<VecDeque<HistorySample>>>,
+    recorder: Mutex<TelemetryRecorder>,
+    events: broadcast::Sender<DaemonEvent>,
+    /// Set once fans are given back to the driver, no duty is written after that
+    fans_released: AtomicBool,
 }
 
-#[self_referencing]
-struct NvmlHandle {
-    nvml: Nvml,
-    #[borrows(nvml)]
-    #[covariant]
-    device: Device<'this>,
+/// Controller state of one GPU kept between control steps
+#[derive(Debug, Clone)]
+struct DeviceControlState {
+    /// Fixed duty held instead of the curve
+    duty_override: Option<DutyOverride>,
+    input_filters: FxHashMap<ControlInput, InputFilter>,
+    /// Duty of each fan the controller asked for last, empty before the first request
+    requested_duties: Vec<u8>,
+    duty_ramp: DutyRamp,
+    /// Duty last set by tjaeled on each fan
+    target_duties: Vec<Option<u8>>,
+    fan_stalls: Vec<FanStall>,
+    /// Takes precedence over the controller and overrides until the alarms are cleared
+    stall_reaction: Option<StallReaction>,
+    /// Newest last, the last one is in progress if it has not ended
+    safety_events: Vec<SafetyEvent>,
+    /// Set from a failed control step until the next successful one
+    degraded: Option<DegradedState>,
+    /// Reported instead of current params when the GPU cannot be read
+    last_runtime: RuntimeGpuParams,
+    /// Monotonic and wall clock time of the previous control step
+    step_clocks: Option<(Instant, SystemTime)>,
+    last_verified: Option<Instant>,
+    /// Fans found changed by something other than tjaeled
+    interference_events: u32,
+    duty_changes: u64,
+    /// Failed control steps, retried or not
+    control_errors: u64,
 }
 
 impl GpuManager {
-    pub fn init<P: AsRef<Path> + Debug>(config_path: P) -> Result<Self> {
+    pub fn init<P: AsRef<Path> + Debug>(
+        config_path: P,
+        backend: Box<dyn GpuBackend>,
+    ) -> Result<Self> {
+        let config_path = config_path.as_ref().to_owned();
         let control_config =
-            TjaeleControlConfig::new_from_file(config_path)?.precompute_fan_curve()?;
+            TjaeleControlConfig::new_from_file(&config_path)?.precompute_fan_curve()?;
 
-        // recommended path for loading nvml
-        let nvml = Nvml::builder().lib_path(OsStr::new("libnvidia-ml.so.1")).init()?;
-        ensure!(
-            nvml.device_count()? == 1,
-            "nvmlcontrol currently supports platforms with one GPU only"
-        );
+        let device_count = backend.device_count();
+        let persistent_params = (0..device_count)
+            .map(|device_idx| backend.read_persistent_params(device_idx))
+            .collect::<Result<Vec<_>>>()?;
 
-        let nvml_handle =
-            NvmlHandleTryBuilder { nvml, device_builder: |nvml: &Nvml| nvml.device_by_index(0) }
-                .try_build()?;
+        for params in &persistent_params {
+            info!(
+                "Found GPU {}: {} (UUID: {}, PCI: {})",
+                params.index, params.device_name, params.uuid, params.pci_bus_id
+            );
+        }
 
-        let persistent_params = nvml_handle.read_persistent_params()?;
+        let control_states = persistent_params
+            .iter()
+            .enumerate()
+            .map(|(device_idx, params)| -> Result<DeviceControlState> {
+                Ok(DeviceControlState {
+                    duty_override: None,
+                    input_filters: FxHashMap::default(),
+                    requested_duties: Vec::new(),
+                    duty_ramp: DutyRamp::new(params.num_fans),
+                    target_duties: vec![None; params.num_fans],
+                    fan_stalls: vec![FanStall::default(); params.num_fans],
+                    stall_reaction: None,
+                    safety_events: Vec::new(),
+                    degraded: None,
+                    last_runtime: backend.read_runtime_params(device_idx, params.num_fans)?,
+                    step_clocks: None,
+                    last_verified: None,
+                    interference_events: 0,
+                    duty_changes: 0,
+                    control_errors: 0,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-        Ok(GpuManager { nvml_handle, persistent_params, control_config })
+        let gpu_manager = GpuManager {
+            backend,
+            config_path,
+            persistent_params,
+            control_config: RwLock::new(control_config),
+            control_states: Mutex::new(control_states),
+            control_generation: AtomicU64::new(0),
+            history: Mutex::new(vec![VecDeque::new(); device_count]),
+            recorder: Mutex::new(TelemetryRecorder::default()),
+            events: broadcast::channel(events::EVENT_CAPACITY).0,
+            fans_released: AtomicBool::new(false),
+        };
+        gpu_manager.check_inputs(&gpu_manager.control_config())?;
+        gpu_manager.warn_unmatched_config(&gpu_manager.control_config());
+
+        Ok(gpu_manager)
+    }
+
+    pub fn device_count(&self) -> usize {
+        self.persistent_params.len()
     }
 
     pub fn read_state(&self) -> Result<GpuState> {
-        Ok(GpuState {
-            runtime: self.nvml_handle.read_runtime_params(self.persistent_params.num_fans)?,
-            persistent: self.persistent_params.clone(),
-            fan_curve: self.control_config.fan_curve.iter().map(|(t, d)| (*t, *d)).collect(),
-        })
+        let control_config = self.control_config().clone();
+        let control_states = self.control_states().clone();
+
+        let devices = self
+            .persistent_params
+            .iter()
+            .enumerate()
+            .map(|(device_idx, persistent)| {
+                let control_state = &control_states[device_idx];
+                let runtime =
+                    match self.backend.read_runtime_params(device_idx, persistent.num_fans) {
+                        Ok(runtime) => {
+                            self.control_states()[device_idx].last_runtime = runtime.clone();
+                            runtime
+                        },
+                        Err(err) => {
+                            warn!("Reporting last known state of GPU {device_idx}: {err:#}");
+                            control_state.last_runtime.clone()
+                        },
+                    };
+
+                self.device_state(device_idx, runtime, &control_config, control_state)
+            })
+            .collect();
+
+        Ok(GpuState { devices })
     }
 
     pub async fn sleep(&self) {
-        tokio::time::sleep(self.control_config.response_time).await;
+        let response_time = self.control_config().response_time;
+        tokio::time::sleep(response_time).await;
     }
-}
 
-impl Drop for GpuManager {
-    fn drop(&mut self) {
-        let device = self.nvml_handle.borrow_device();
+    /// Validates and precomputes the config, then swaps it with the one in use
+    pub fn replace_config(&self, control_config: TjaeleControlConfig) -> Result<()> {
+        let control_config = control_config.validate()?.precompute_fan_curve()?;
+        self.check_inputs(&control_config)?;
+        self.warn_unmatched_config(&control_config);
+
+        self.swap_config(control_config);
+        info!("Control config replaced");
+
+        Ok(())
+    }
+
+    /// Reads the config file again and applies it, fan policy is not touched
+    pub fn reload_config(&self) -> Result<()> {
+        let control_config = TjaeleControlConfig::new_from_file(&self.config_path)?;
+        self.replace_config(control_config)
+    }
 
-        for fan_idx in 0..self.persistent_params.num_fans {
-            device.set_default_fan_speed(fan_idx as u32)
-                    // We panic here on purpose, so that failure "wreaks havoc"
-                    // Ignoring error here could be potentially dangerous for the GPU
-                    .expect("Failed to set auto fan control policy upon nvmlcontrol shutdown");
+    /// Validates and precomputes the curve, then swaps it with the one in use
+    /// for given GPU (or the default curve if no GPU is specified)
+    pub fn replace_fan_curve(&self, update: FanCurveUpdate) -> Result<()> {
+        let fan_curve = update.fan_curve.into_iter().collect();
+        control_config::validate_anchor_points(&fan_curve)?;
+        let anchor_points = fan_curve::sorted_anchor_points(&fan_curve);
+        let fan_curve = fan_curve::precompute_fan_curve(&fan_curve)?;
+
+        // held for the whole update, so concurrent config changes are not lost
+        let mut control_config =
+            self.control_config.write().unwrap_or_else(PoisonError::into_inner);
+
+        match update.device {
+            None => {
+                control_config.fan_curve = fan_curve;
+                control_config.anchor_points = anchor_points;
+            },
+            Some(id) => {
+                let params = self
+                    .persistent_params
+                    .iter()
+                    .find(|params| DeviceControlConfig::id_matches(&id, params))
+                    .with_context(|| format!("GPU {id} not found"))?;
+
+                // the rest of the GPU config is kept
+                let device_config = match control_config.device_config(params) {
+                    Some(device_config) => {
+                        DeviceControlConfig { fan_curve, anchor_points, ..device_config.clone() }
+                    },
+                    None => DeviceControlConfig {
+                        id,
+                        fan_curve,
+                        anchor_points,
+                        mode: None,
+                        pid: None,
+                        inputs: None,
+                        fans: None,
+                    },
+                };
+
+                control_config.devices.retain(|device_config| !device_config.matches(params));
+                control_config.devices.push(device_config);
+            },
         }
-        info!("All fans policy set to automatic");
+        drop(control_config);
+
+        self.config_changed();
+        info!("Fan curve replaced");
+
+        Ok(())
     }
-}
 
-impl Debug for NvmlHandle {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("NvmlHandle")
-            .field("nvml", &self.borrow_nvml())
-            .field("device", &self.borrow_device())
-            .finish()
+    /// Allows the fan controller to notice that the curve duty it set is no longer current
+    pub fn control_generation(&self) -> u64 {
+        self.control_generation.load(Ordering::Acquire)
     }
-}
 
-#[serde_as]
-#[derive(Debug, Clone, Deserialize)]
-pub struct TjaeleControlConfig {
-    #[serde_as(as = "serde_with::DurationSecondsWithFrac<f64>")]
-    pub response_time: Duration,
-    pub hysteresis: u16,
-    #[serde_as(as = "Vec<(_, _)>")]
-    pub fan_curve: FxHashMap<u8, u8>,
-}
+    fn control_config(&self) -> RwLockReadGuard<'_, TjaeleControlConfig> {
+        // config is never partially modified, so poisoning cannot leave it inconsistent
+        self.control_config.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn control_states(&self) -> MutexGuard<'_, Vec<DeviceControlState>> {
+        // states are never partially modified, so poisoning cannot leave them inconsistent
+        self.control_states.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn swap_config(&self, control_config: TjaeleControlConfig) {
+        *self.control_config.write().unwrap_or_else(PoisonError::into_inner) = control_config;
+        self.config_changed();
+    }
+
+    /// Lets fan controllers and subscribers know that the config in use was changed
+    fn config_changed(&self) {
+        self.bump_control_generation();
+        self.publish(DaemonEvent::ConfigChanged);
+    }
 
-impl TjaeleControlConfig {
-    pub(self) fn new_from_file<Q: AsRef<Path> + Debug>(path: Q) -> Result<Self> {
-        let cfg = std::fs::read_to_string(&path)?;
-        let cfg: Self = toml::from_str(&cfg)?;
+    fn device_state(
+        &self,
+        device_idx: usize,
+        mut runtime: RuntimeGpuParams,
+        control_config: &TjaeleControlConfig,
+        control_state: &DeviceControlState,
+    ) -> DeviceState {
+        let persistent = &self.persistent_params[device_idx];
 
-        ensure!(cfg.hysteresis > 0 && cfg.hysteresis <= 5, "Hysteresis must be between 1C and 5C");
+        runtime.filtered_temperature = control_state
+            .input_filters
+            .get(&ControlInput::CoreTemperature)
+            .and_then(InputFilter::filtered);
+        for fan_state in &mut runtime.fan_states {
+            fan_state.target_duty =
+                control_state.target_duties.get(fan_state.index).copied().flatten();
+        }
+
+        DeviceState {
+            runtime,
+            persistent: persistent.clone(),
+            fan_curve: control_config
+                .fan_curve_for(persistent)
+                .iter()
+                .map(|(t, d)| (*t, *d))
+                .collect(),
+            fan_curve_anchors: control_config.anchor_points_for(persistent).to_vec(),
+            control_mode: control_config.mode_for(persistent),
+            duty_override: control_state.duty_override.as_ref().map(DutyOverride::to_active),
+            fan_alarms: control_state
+                .fan_stalls
+                .iter()
+                .filter_map(|fan_stall| fan_stall.alarm.clone())
+                .collect(),
+            safety_events: control_state.safety_events.clone(),
+            degraded: control_state.degraded.clone(),
+            interference_events: control_state.interference_events,
+            duty_changes: control_state.duty_changes,
+            control_errors: control_state.control_errors,
+        }
+    }
+
+    /// Writes the duty and remembers it, so that it is only written again when it changes
+    fn set_fan_duty(
+        &self,
+        device_idx: usize,
+        control_state: &mut DeviceControlState,
+        fan_idx: usize,
+        duty: u8,
+    ) -> Result<()> {
         ensure!(
-            cfg.response_time.as_secs_f64() >= 0.25,
-            "Response time must be at least than 0.25 seconds"
+            !self.fans_released.load(Ordering::Acquire),
+            "Fans were already given back to the driver"
         );
+        self.backend
+            .set_fan_duty(device_idx, fan_idx, u32::from(duty))
+            .context("Failed to set fan speed")?;
 
-        cfg.fan_curve.iter().try_for_each(|(_, &fan_duty)| -> Result<()> {
-            ensure!(fan_duty <= 100, "Fan duty cannot be higher than 100%");
-            Ok(())
-        })?;
+        if control_state.target_duties[fan_idx] != Some(duty) {
+            control_state.target_duties[fan_idx] = Some(duty);
+            control_state.duty_changes += 1;
+            self.publish(DaemonEvent::DutyChanged { device: device_idx, fan: fan_idx, duty });
+        }
+
+        Ok(())
+    }
 
-        ensure!(cfg.fan_curve.len() >= 3, "Fan curve must have at least 3 points");
+    fn bump_control_generation(&self) {
+        self.control_generation.fetch_add(1, Ordering::AcqRel);
+    }
 
-        info!("Config loaded from {path:?}");
+    /// Inputs not reported by a GPU would stop its fan controller, so such config is rejected
+    fn check_inputs(&self, control_config: &TjaeleControlConfig) -> Result<()> {
+        for (device_idx, params) in self.persistent_params.iter().enumerate() {
+            for input in control_config.inputs_for(params) {
+                self.backend.read_input(device_idx, input.source).with_context(|| {
+                    format!("GPU {} cannot be controlled by {}", params.index, input.source)
+                })?;
+            }
 
-        Ok(cfg)
+            if control_config.mode_for(params) == ControlMode::Pid {
+                if let Some(pid_config) = control_config.pid_for(params) {
+                    self.backend.read_input(device_idx, pid_config.source).with_context(|| {
+                        format!(
+                            "GPU {} cannot be controlled by {}",
+                            params.index, pid_config.source
+                        )
+                    })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn warn_unmatched_config(&self, control_config: &TjaeleControlConfig) {
+        for device_config in &control_config.devices {
+            if !self.persistent_params.iter().any(|params| device_config.matches(params)) {
+                warn!("Config has a fan curve for GPU {} which is not present", device_config.id);
+            }
+        }
+
+        for params in &self.persistent_params {
+            for fan_config in control_config.fan_configs_for(params) {
+                if fan_config.index >= params.num_fans {
+                    warn!(
+                        "Config has adjustments for fan {} which GPU {} does not have",
+                        fan_config.index, params.index
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl GpuManager {
+    /// Sets automatic policy on all fans, so that the driver controls them after tjaeled exits.
+    ///
+    /// Every fan is tried even if some fail, as a lost GPU must not leave fans
+    /// of the healthy ones in manual mode. No duty is written afterwards.
+    pub fn release_fans(&self) -> Result<()> {
+        // held so that no control step writes a duty in between
+        let _control_states = self.control_states();
+        self.fans_released.store(true, Ordering::Release);
+
+        let mut failed = 0;
+        for (device_idx, params) in self.persistent_params.iter().enumerate() {
+            for fan_idx in 0..params.num_fans {
+                if let Err(err) = self.backend.set_default_fan_policy(device_idx, fan_idx) {
+                    error!(
+                        "Failed to set automatic policy of GPU {device_idx} fan {fan_idx}: {err:#}"
+                    );
+                    failed += 1;
+                }
+            }
+        }
+
+        ensure!(failed == 0, "{failed} fans could not be returned to automatic policy");
+        info!("All fans policy set to automatic");
+
+        Ok(())
+    }
+}
+
+impl Drop for GpuManager {
+    fn drop(&mut self) {
+        // the daemon releases fans before it exits, this covers every other way out
+        if self.fans_released.load(Ordering::Acquire) {
+            return;
+        }
+
+        if let Err(err) = self.release_fans() {
+            error!("{err:#}");
+        }
     }
 }
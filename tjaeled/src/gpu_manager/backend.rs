@@ -0,0 +1,99 @@
+use std::{fmt::Debug, sync::Arc};
+
+mod nvml;
+mod simulated;
+
+use anyhow::Result;
+use tjaele_types::{ControlInput, FanControlPolicy, PersistentGpuParams, RuntimeGpuParams};
+
+pub use nvml::NvmlHandle;
+pub use simulated::SimulatedBackend;
+
+/// Hardware access required by the fan controller.
+///
+/// All devices are addressed by their index, which is stable for the lifetime of the backend.
+pub trait GpuBackend: Debug + Send + Sync {
+    fn device_count(&self) -> usize;
+
+    fn read_persistent_params(&self, device_idx: usize) -> Result<PersistentGpuParams>;
+
+    fn read_runtime_params(&self, device_idx: usize, num_fans: usize) -> Result<RuntimeGpuParams>;
+
+    /// Core temperature in Celsius
+    fn temperature(&self, device_idx: usize) -> Result<u32>;
+
+    /// Celsius for temperatures, percent of the enforced power limit for power
+    fn read_input(&self, device_idx: usize, input: ControlInput) -> Result<u32>;
+
+    /// Actual fan speed (0-100)
+    fn fan_speed(&self, device_idx: usize, fan_idx: usize) -> Result<u32>;
+
+    /// Duty the fan was last set to (0-100), by anyone
+    fn fan_duty(&self, device_idx: usize, fan_idx: usize) -> Result<u32>;
+
+    fn fan_control_policy(&self, device_idx: usize, fan_idx: usize) -> Result<FanControlPolicy>;
+
+    /// Disables automatic fan control and sets provided duty (0-100)
+    fn set_fan_duty(&self, device_idx: usize, fan_idx: usize, duty: u32) -> Result<()>;
+
+    /// Gives fan control back to the driver
+    fn set_default_fan_policy(&self, device_idx: usize, fan_idx: usize) -> Result<()>;
+
+    /// Whether the error can go away on its own (GPU lost, driver reloaded, ...)
+    fn is_retryable(&self, err: &anyhow::Error) -> bool;
+
+    /// Connects to the devices again, fails if device indices would no longer
+    /// point to the same GPUs
+    fn reinit(&self) -> Result<()>;
+}
+
+/// Shared backend, eg. kept by tests to inspect fans after `GpuManager` is gone
+impl<T: GpuBackend + ?Sized> GpuBackend for Arc<T> {
+    fn device_count(&self) -> usize {
+        (**self).device_count()
+    }
+
+    fn read_persistent_params(&self, device_idx: usize) -> Result<PersistentGpuParams> {
+        (**self).read_persistent_params(device_idx)
+    }
+
+    fn read_runtime_params(&self, device_idx: usize, num_fans: usize) -> Result<RuntimeGpuParams> {
+        (**self).read_runtime_params(device_idx, num_fans)
+    }
+
+    fn temperature(&self, device_idx: usize) -> Result<u32> {
+        (**self).temperature(device_idx)
+    }
+
+    fn read_input(&self, device_idx: usize, input: ControlInput) -> Result<u32> {
+        (**self).read_input(device_idx, input)
+    }
+
+    fn fan_speed(&self, device_idx: usize, fan_idx: usize) -> Result<u32> {
+        (**self).fan_speed(device_idx, fan_idx)
+    }
+
+    fn fan_duty(&self, device_idx: usize, fan_idx: usize) -> Result<u32> {
+        (**self).fan_duty(device_idx, fan_idx)
+    }
+
+    fn fan_control_policy(&self, device_idx: usize, fan_idx: usize) -> Result<FanControlPolicy> {
+        (**self).fan_control_policy(device_idx, fan_idx)
+    }
+
+    fn set_fan_duty(&self, device_idx: usize, fan_idx: usize, duty: u32) -> Result<()> {
+        (**self).set_fan_duty(device_idx, fan_idx, duty)
+    }
+
+    fn set_default_fan_policy(&self, device_idx: usize, fan_idx: usize) -> Result<()> {
+        (**self).set_default_fan_policy(device_idx, fan_idx)
+    }
+
+    fn is_retryable(&self, err: &anyhow::Error) -> bool {
+        (**self).is_retryable(err)
+    }
+
+    fn reinit(&self) -> Result<()> {
+        (**self).reinit()
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    ffi::OsStr,
+    fmt::Debug,
+    sync::{PoisonError, RwLock, RwLockReadGuard},
+};
+
+mod device_probe;
+mod intermediate_bindings;
+
+use anyhow::{ensure, Context, Result};
+use intermediate_bindings::AdditionalNvmlFunctionality;
+use nvml_wrapper::{enum_wrappers::device::TemperatureSensor, error::NvmlError, Device, Nvml};
+use ouroboros::self_referencing;
+use tjaele_types::{ControlInput, FanControlPolicy, PersistentGpuParams, RuntimeGpuParams};
+use tracing::info;
+
+use super::GpuBackend;
+
+/// Handle to NVML and all GPUs it reports.
+///
+/// The handle can be initialised again after the driver went away, as long as
+/// the same GPUs come back in the same order.
+pub struct NvmlHandle {
+    devices: RwLock<NvmlDevices>,
+    /// Identify the GPUs behind device indices
+    uuids: Vec<String>,
+}
+
+#[self_referencing]
+struct NvmlDevices {
+    nvml: Nvml,
+    #[borrows(nvml)]
+    #[covariant]
+    devices: Vec<Device<'this>>,
+}
+
+impl NvmlHandle {
+    pub fn init() -> Result<Self> {
+        let devices = NvmlDevices::init()?;
+        let uuids = devices.uuids()?;
+
+        Ok(NvmlHandle { devices: RwLock::new(devices), uuids })
+    }
+
+    fn devices(&self) -> RwLockReadGuard<'_, NvmlDevices> {
+        // devices are only ever replaced as a whole
+        self.devices.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl NvmlDevices {
+    fn init() -> Result<Self> {
+        // recommended path for loading nvml
+        let nvml = Nvml::builder().lib_path(OsStr::new("libnvidia-ml.so.1")).init()?;
+        let device_count = nvml.device_count()?;
+        ensure!(device_count > 0, "No Nvidia GPUs found");
+
+        let nvml_devices = NvmlDevicesTryBuilder {
+            nvml,
+            devices_builder: |nvml: &Nvml| {
+                (0..device_count).map(|index| nvml.device_by_index(index)).collect()
+            },
+        }
+        .try_build()?;
+
+        Ok(nvml_devices)
+    }
+
+    fn device(&self, device_idx: usize) -> Result<&Device<'_>> {
+        self.borrow_devices().get(device_idx).with_context(|| format!("GPU {device_idx} not found"))
+    }
+
+    fn uuids(&self) -> Result<Vec<String>> {
+        self.borrow_devices()
+            .iter()
+            .map(|device| device.uuid().context("Failed to read GPU UUID"))
+            .collect()
+    }
+}
+
+impl GpuBackend for NvmlHandle {
+    fn device_count(&self) -> usize {
+        self.uuids.len()
+    }
+
+    fn read_persistent_params(&self, device_idx: usize) -> Result<PersistentGpuParams> {
+        self.devices().probe_persistent_params(device_idx)
+    }
+
+    fn read_runtime_params(&self, device_idx: usize, num_fans: usize) -> Result<RuntimeGpuParams> {
+        self.devices().probe_runtime_params(device_idx, num_fans)
+    }
+
+    fn temperature(&self, device_idx: usize) -> Result<u32> {
+        self.devices()
+            .device(device_idx)?
+            .temperature(TemperatureSensor::Gpu)
+            .context("Failed to read GPU temperature")
+    }
+
+    fn read_input(&self, device_idx: usize, input: ControlInput) -> Result<u32> {
+        match input {
+            ControlInput::CoreTemperature => self.temperature(device_idx),
+            ControlInput::MemoryTemperature => self
+                .devices()
+                .read_memory_temperature(device_idx)
+                .context("Failed to read GPU memory temperature"),
+            ControlInput::Power => self.devices().read_power_percentage(device_idx),
+        }
+    }
+
+    fn fan_speed(&self, device_idx: usize, fan_idx: usize) -> Result<u32> {
+        self.devices()
+            .device(device_idx)?
+            .fan_speed(fan_idx as u32)
+            .with_context(|| format!("Failed to read fan_{fan_idx} speed"))
+    }
+
+    fn fan_duty(&self, device_idx: usize, fan_idx: usize) -> Result<u32> {
+        self.devices()
+            .device(device_idx)?
+            .fan_duty(fan_idx as u32)
+            .with_context(|| format!("Failed to read fan_{fan_idx} duty"))
+    }
+
+    fn fan_control_policy(&self, device_idx: usize, fan_idx: usize) -> Result<FanControlPolicy> {
+        Ok(self
+            .devices()
+            .device(device_idx)?
+            .fan_control_policy(fan_idx as u32)
+            .with_context(|| format!("Failed to read fan_{fan_idx} policy"))?
+            .into())
+    }
+
+    fn set_fan_duty(&self, device_idx: usize, fan_idx: usize, duty: u32) -> Result<()> {
+        self.devices()
+            .device(device_idx)?
+            .set_fan_speed(fan_idx as u32, duty)
+            .with_context(|| format!("Failed to set fan_{fan_idx} speed"))
+    }
+
+    fn set_default_fan_policy(&self, device_idx: usize, fan_idx: usize) -> Result<()> {
+        self.devices()
+            .device(device_idx)?
+            .set_default_fan_speed(fan_idx as u32)
+            .with_context(|| format!("Failed to set fan_{fan_idx} policy to automatic"))
+    }
+
+    fn is_retryable(&self, err: &anyhow::Error) -> bool {
+        let nvml_error = err.chain().find_map(|cause| cause.downcast_ref::<NvmlError>());
+
+        // errors of a GPU or driver in transition, the rest will not go away by waiting
+        matches!(
+            nvml_error,
+            Some(
+                NvmlError::GpuLost
+                    | NvmlError::Timeout
+                    | NvmlError::Unknown
+                    | NvmlError::Uninitialized
+                    | NvmlError::DriverNotLoaded
+                    | NvmlError::LibRmVersionMismatch
+                    | NvmlError::IrqIssue
+            )
+        )
+    }
+
+    fn reinit(&self) -> Result<()> {
+        let devices = NvmlDevices::init().context("Failed to initialise NVML again")?;
+        let uuids = devices.uuids()?;
+        ensure!(
+            uuids == self.uuids,
+            "GPUs changed after NVML initialisation ({:?} instead of {:?})",
+            uuids,
+            self.uuids
+        );
+
+        *self.devices.write().unwrap_or_else(PoisonError::into_inner) = devices;
+        info!("NVML initialised again");
+
+        Ok(())
+    }
+}
+
+impl Debug for NvmlHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NvmlHandle")
+            .field("devices", &*self.devices())
+            .field("uuids", &self.uuids)
+            .finish()
+    }
+}
+
+impl Debug for NvmlDevices {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NvmlDevices")
+            .field("nvml", &self.borrow_nvml())
+            .field("devices", &self.borrow_devices())
+            .finish()
+    }
+}
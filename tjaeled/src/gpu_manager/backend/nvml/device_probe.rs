@@ -1,30 +1,38 @@
 use super::{
-    intermediate_bindings::AdditionalNvmlFunctionality, ouroboros_impl_nvml_handle::NvmlHandle,
+    intermediate_bindings::AdditionalNvmlFunctionality, ouroboros_impl_nvml_devices::NvmlDevices,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use chrono::Local;
 use nvml_wrapper::{
     cuda_driver_version_major, cuda_driver_version_minor,
     enum_wrappers::device::{Clock, TemperatureSensor, TemperatureThreshold},
+    enums::device::SampleValue,
+    structs::device::FieldId,
+    sys_exports::field_id::NVML_FI_DEV_MEMORY_TEMP,
 };
 use tjaele_types::{
     ClockSpeeds, CudaVersion, FanState, GpuTemperatureThresholds, PCIeLink, PersistentGpuParams,
     RuntimeGpuParams, SysInfo,
 };
 
-impl NvmlHandle {
-    pub(super) fn read_persistent_params(&self) -> Result<PersistentGpuParams> {
-        let device = self.borrow_device();
+impl NvmlDevices {
+    pub(super) fn probe_persistent_params(&self, device_idx: usize) -> Result<PersistentGpuParams> {
+        let device = self.device(device_idx)?;
 
         Ok(PersistentGpuParams {
-            sys_info: self.read_sys_info()?,
+            sys_info: self.read_sys_info(device_idx)?,
 
+            index: device_idx,
+            uuid: device.uuid().context("Failed to read GPU UUID")?,
+            pci_bus_id: device.pci_info().context("Failed to read GPU PCI info")?.bus_id,
             device_name: device.name().context("Failed to read GPU name")?,
             architecture: device.architecture().context("Failed to read GPU arch")?.into(),
             num_cores: device.num_cores().context("Failed to read GPU num cores")?,
             num_fans: device.num_fans().context("Failed to read GPU num fans")? as usize,
 
-            max_pcie_link: self.read_max_pcie_link().context("Failed to read GPU max PCIe link")?,
+            max_pcie_link: self
+                .read_max_pcie_link(device_idx)
+                .context("Failed to read GPU max PCIe link")?,
 
             temp_thresholds: GpuTemperatureThresholds {
                 shutdown: device
@@ -44,31 +52,69 @@ impl NvmlHandle {
         })
     }
 
-    pub(super) fn read_runtime_params(&self, num_fans: usize) -> Result<RuntimeGpuParams> {
-        let device = self.borrow_device();
+    pub(super) fn probe_runtime_params(
+        &self,
+        device_idx: usize,
+        num_fans: usize,
+    ) -> Result<RuntimeGpuParams> {
+        let device = self.device(device_idx)?;
 
         Ok(RuntimeGpuParams {
             probe_time: Local::now(),
             current_pcie_link: self
-                .read_current_pcie_link()
+                .read_current_pcie_link(device_idx)
                 .context("Failed to read GPU PCIe link info")?,
             memory_info: device.memory_info().context("Failed to read GPU memory info")?.into(),
             power_usage: f64::from(device.power_usage().context("Failed to read GPU power usage")?)
                 / 1000.0,
-            clock_speeds: self.read_clock_speeds().context("Failed to read GPU clock speeds")?,
+            power_limit: device.enforced_power_limit().ok().map(|limit| f64::from(limit) / 1000.0),
+            clock_speeds: self
+                .read_clock_speeds(device_idx)
+                .context("Failed to read GPU clock speeds")?,
             device_temperature: device
                 .temperature(TemperatureSensor::Gpu)
                 .context("Failed to read GPU temperature")?,
+            // filled in by GpuManager
+            filtered_temperature: None,
+            memory_temperature: self.read_memory_temperature(device_idx).ok(),
             fan_states: (0..num_fans)
-                .map(|index| -> Result<FanState> { self.read_fan_state(index) })
+                .map(|index| -> Result<FanState> { self.read_fan_state(device_idx, index) })
                 .collect::<Result<Vec<_>>>()
                 .context("Failed to read GPU fan states")?,
         })
     }
 
-    fn read_sys_info(&self) -> Result<SysInfo> {
+    /// Only available through field values, which not all GPUs report
+    pub(super) fn read_memory_temperature(&self, device_idx: usize) -> Result<u32> {
+        let device = self.device(device_idx)?;
+
+        let sample = device
+            .field_values_for(&[FieldId(NVML_FI_DEV_MEMORY_TEMP)])?
+            .pop()
+            .context("Memory temperature field missing")??;
+
+        match sample.value? {
+            SampleValue::U32(temp) => Ok(temp),
+            SampleValue::U64(temp) => Ok(u32::try_from(temp)?),
+            SampleValue::I64(temp) => Ok(u32::try_from(temp)?),
+            SampleValue::F64(_) => Err(anyhow!("Unexpected memory temperature format")),
+        }
+    }
+
+    /// Percent of the enforced power limit
+    pub(super) fn read_power_percentage(&self, device_idx: usize) -> Result<u32> {
+        let device = self.device(device_idx)?;
+
+        let power_usage = device.power_usage().context("Failed to read GPU power usage")?;
+        let power_limit =
+            device.enforced_power_limit().context("Failed to read GPU power limit")?.max(1);
+
+        Ok(power_usage.saturating_mul(100) / power_limit)
+    }
+
+    fn read_sys_info(&self, device_idx: usize) -> Result<SysInfo> {
         let nvml = self.borrow_nvml();
-        let device = self.borrow_device();
+        let device = self.device(device_idx)?;
 
         Ok(SysInfo {
             driver_version: nvml.sys_driver_version()?,
@@ -88,8 +134,8 @@ impl NvmlHandle {
         })
     }
 
-    pub(self) fn read_max_pcie_link(&self) -> Result<PCIeLink> {
-        let device = self.borrow_device();
+    pub(self) fn read_max_pcie_link(&self, device_idx: usize) -> Result<PCIeLink> {
+        let device = self.device(device_idx)?;
 
         Ok(PCIeLink {
             gen: device.max_pcie_link_gen()?,
@@ -103,8 +149,8 @@ impl NvmlHandle {
         })
     }
 
-    fn read_current_pcie_link(&self) -> Result<PCIeLink> {
-        let device = self.borrow_device();
+    fn read_current_pcie_link(&self, device_idx: usize) -> Result<PCIeLink> {
+        let device = self.device(device_idx)?;
 
         Ok(PCIeLink {
             gen: device.current_pcie_link_gen()?,
@@ -113,8 +159,8 @@ impl NvmlHandle {
         })
     }
 
-    fn read_clock_speeds(&self) -> Result<ClockSpeeds> {
-        let device = self.borrow_device();
+    fn read_clock_speeds(&self, device_idx: usize) -> Result<ClockSpeeds> {
+        let device = self.device(device_idx)?;
 
         Ok(ClockSpeeds {
             memory: device.clock_info(Clock::Memory)?,
@@ -124,8 +170,8 @@ impl NvmlHandle {
         })
     }
 
-    fn read_fan_state(&self, index: usize) -> Result<FanState> {
-        let device = self.borrow_device();
+    fn read_fan_state(&self, device_idx: usize, index: usize) -> Result<FanState> {
+        let device = self.device(device_idx)?;
 
         Ok(FanState {
             index,
@@ -135,6 +181,8 @@ impl NvmlHandle {
             duty: device
                 .fan_duty(index as u32)
                 .with_context(|| format!("Failed to read fan_{index} duty"))?,
+            // filled in by GpuManager
+            target_duty: None,
             control_policy: device
                 .fan_control_policy(index as u32)
                 .with_context(|| format!("Failed to read fan_{index} policy"))?
@@ -0,0 +1,504 @@
+#![allow(clippy::cast_sign_loss)]
+
+use std::{
+    fmt::{Debug, Display},
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::Instant,
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use chrono::Local;
+use serde::Deserialize;
+use tjaele_types::{
+    ClockSpeeds, ControlInput, CudaComputeCapability, CudaVersion, FanControlPolicy, FanState,
+    GpuArchitecture, GpuMemStats, GpuTemperatureThresholds, MinMaxFanSpeeds, PCIeLink,
+    PersistentGpuParams, RuntimeGpuParams, SysInfo,
+};
+use tracing::info;
+
+use super::GpuBackend;
+
+/// Longest step of the thermal model integration (in simulated seconds)
+const MAX_STEP: f64 = 0.1;
+
+/// Backend which models GPUs with a simple lumped thermal model.
+///
+/// Heat is generated according to the load schedule from the scenario and removed
+/// proportionally to the difference between GPU and ambient temperature. The fans
+/// increase the heat removal and follow their duty with a first-order lag.
+#[derive(Debug)]
+pub struct SimulatedBackend {
+    scenario: SimulationScenario,
+    state: Mutex<SimulationState>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulationScenario {
+    /// How many times faster than real time the simulation runs
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f64,
+    /// Celsius
+    pub ambient_temperature: f64,
+    #[serde(rename = "device")]
+    pub devices: Vec<SimulatedGpuConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulatedGpuConfig {
+    pub name: String,
+    pub num_fans: usize,
+    /// Heat capacity of GPU with its cooler (J/C)
+    pub heat_capacity: f64,
+    /// Heat removed by the cooler with fans stopped (W/C)
+    pub passive_conductance: f64,
+    /// Heat additionally removed by the cooler at 100% fan speed (W/C)
+    pub fan_conductance: f64,
+    /// Time constant of fans reaching their duty (seconds)
+    pub fan_time_constant: f64,
+    /// Power draw as steps of [SECONDS_SINCE_START, POWER(W)]
+    pub load: Vec<(f64, f64)>,
+    /// Repeat the load schedule with given period (seconds)
+    pub load_period: Option<f64>,
+    /// Power limit (W), the highest power from the load schedule if not set
+    pub power_limit: Option<f64>,
+    /// Fans which stop spinning as [[FAN_INDEX, SECONDS_SINCE_START]]
+    #[serde(default)]
+    pub fan_failures: Vec<(usize, f64)>,
+    /// Times when the driver switches all fans to automatic policy, as it may on resume
+    /// (seconds since start)
+    #[serde(default)]
+    pub policy_resets: Vec<f64>,
+    /// Times when another tool sets all fans to a duty as [[SECONDS_SINCE_START, DUTY]]
+    #[serde(default)]
+    pub duty_changes: Vec<(f64, u32)>,
+    /// Periods when the GPU cannot be reached as [[START, END]] in seconds since start
+    #[serde(default)]
+    pub outages: Vec<(f64, f64)>,
+    /// How much hotter than the core the memory gets at full load (Celsius),
+    /// memory temperature is not reported if not set
+    pub memory_temperature_rise: Option<f64>,
+    #[serde(default = "default_temp_thresholds")]
+    pub temp_thresholds: GpuTemperatureThresholds,
+    #[serde(default = "default_minmax_fan_speeds")]
+    pub minmax_fan_speeds: MinMaxFanSpeeds,
+}
+
+#[derive(Debug)]
+struct SimulationState {
+    clock: SimulationClock,
+    /// Simulated seconds since the start the GPUs were advanced to
+    elapsed: f64,
+    gpus: Vec<SimulatedGpu>,
+}
+
+/// Source of simulated time
+#[derive(Debug)]
+enum SimulationClock {
+    /// Real time since the start, sped up by the time scale
+    Real(Instant),
+    /// Simulated seconds since the start, moved only by `SimulatedBackend::advance`
+    #[cfg_attr(not(test), allow(dead_code))]
+    Manual(f64),
+}
+
+#[derive(Debug)]
+struct SimulatedGpu {
+    temperature: f64,
+    power: f64,
+    fans: Vec<SimulatedFan>,
+}
+
+#[derive(Debug)]
+struct SimulatedFan {
+    speed: f64,
+    duty: u32,
+    manual: bool,
+}
+
+impl SimulatedBackend {
+    pub fn new_from_file<P: AsRef<Path> + Debug>(path: P) -> Result<Self> {
+        let scenario = std::fs::read_to_string(&path)?;
+        let scenario: SimulationScenario = toml::from_str(&scenario)?;
+
+        ensure!(scenario.time_scale > 0.0, "Time scale must be positive");
+        ensure!(!scenario.devices.is_empty(), "Simulation must have at least one GPU");
+
+        for gpu in &scenario.devices {
+            ensure!(gpu.heat_capacity > 0.0, "Heat capacity of {} must be positive", gpu.name);
+            ensure!(gpu.passive_conductance > 0.0, "Conductance of {} must be positive", gpu.name);
+            ensure!(
+                gpu.fan_conductance >= 0.0,
+                "Fan conductance of {} cannot be negative",
+                gpu.name
+            );
+            ensure!(gpu.fan_time_constant > 0.0, "Fan time constant must be positive");
+            ensure!(!gpu.load.is_empty(), "Load schedule of {} cannot be empty", gpu.name);
+            ensure!(
+                gpu.power_limit.is_none_or(|limit| limit > 0.0),
+                "Power limit of {} must be positive",
+                gpu.name
+            );
+        }
+
+        let gpus = scenario
+            .devices
+            .iter()
+            .map(|gpu| SimulatedGpu {
+                temperature: scenario.ambient_temperature,
+                power: 0.0,
+                fans: (0..gpu.num_fans)
+                    .map(|_| SimulatedFan { speed: 0.0, duty: 0, manual: false })
+                    .collect(),
+            })
+            .collect();
+
+        info!("Simulation scenario loaded from {path:?}");
+
+        Ok(SimulatedBackend {
+            scenario,
+            state: Mutex::new(SimulationState {
+                clock: SimulationClock::Real(Instant::now()),
+                elapsed: 0.0,
+                gpus,
+            }),
+        })
+    }
+
+    /// Stops following real time, so that tests control the simulation with `advance`
+    #[cfg(test)]
+    pub fn with_manual_clock(self) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.clock = SimulationClock::Manual(state.elapsed);
+        }
+
+        self
+    }
+
+    /// Moves the manual clock forward by `seconds` of simulated time
+    #[cfg(test)]
+    pub fn advance(&self, seconds: f64) {
+        let mut state = self.state.lock().unwrap();
+        if let SimulationClock::Manual(elapsed) = &mut state.clock {
+            *elapsed += seconds;
+        }
+    }
+
+    /// Advances the simulation up to now and returns its state
+    fn state(&self) -> Result<MutexGuard<'_, SimulationState>> {
+        let mut state = self.state.lock().map_err(|_| anyhow!("Simulation state poisoned"))?;
+
+        let mut elapsed = state.elapsed;
+        let mut remaining = state.clock.elapsed(self.scenario.time_scale) - elapsed;
+
+        while remaining > 0.0 {
+            let dt = remaining.min(MAX_STEP);
+            elapsed += dt;
+            remaining -= dt;
+
+            for (gpu, config) in state.gpus.iter_mut().zip(&self.scenario.devices) {
+                gpu.step(config, self.scenario.ambient_temperature, elapsed, dt);
+            }
+        }
+
+        state.elapsed = elapsed;
+
+        Ok(state)
+    }
+
+    /// Same as `state`, but fails while the GPU is in an outage
+    fn device_state(&self, device_idx: usize) -> Result<MutexGuard<'_, SimulationState>> {
+        let config = self.config(device_idx)?;
+        let state = self.state()?;
+
+        let elapsed = state.elapsed;
+        if config.outages.iter().any(|&(start, end)| (start..end).contains(&elapsed)) {
+            return Err(SimulatedOutage { device_idx }.into());
+        }
+
+        Ok(state)
+    }
+
+    fn config(&self, device_idx: usize) -> Result<&SimulatedGpuConfig> {
+        self.scenario.devices.get(device_idx).with_context(|| format!("GPU {device_idx} not found"))
+    }
+}
+
+impl SimulationState {
+    fn fan(&mut self, device_idx: usize, fan_idx: usize) -> Result<&mut SimulatedFan> {
+        self.gpus
+            .get_mut(device_idx)
+            .with_context(|| format!("GPU {device_idx} not found"))?
+            .fans
+            .get_mut(fan_idx)
+            .with_context(|| format!("Fan {fan_idx} of GPU {device_idx} not found"))
+    }
+}
+
+impl SimulationClock {
+    /// Simulated seconds since the start
+    fn elapsed(&self, time_scale: f64) -> f64 {
+        match self {
+            SimulationClock::Real(started) => started.elapsed().as_secs_f64() * time_scale,
+            SimulationClock::Manual(elapsed) => *elapsed,
+        }
+    }
+}
+
+impl SimulatedGpu {
+    fn memory_temperature(&self, config: &SimulatedGpuConfig) -> Option<f64> {
+        config
+            .memory_temperature_rise
+            .map(|rise| self.temperature + rise * self.power / config.power_limit())
+    }
+
+    fn step(&mut self, config: &SimulatedGpuConfig, ambient: f64, elapsed: f64, dt: f64) {
+        self.power = config.power_at(elapsed);
+
+        let policy_reset =
+            config.policy_resets.iter().any(|&at| (elapsed - dt..elapsed).contains(&at));
+
+        let duty_change = config
+            .duty_changes
+            .iter()
+            .find(|(at, _)| (elapsed - dt..elapsed).contains(at))
+            .map(|&(_, duty)| duty.min(100));
+
+        for (fan_idx, fan) in self.fans.iter_mut().enumerate() {
+            if policy_reset {
+                fan.manual = false;
+            }
+            if let Some(duty) = duty_change {
+                fan.duty = duty;
+                fan.manual = true;
+            }
+            if !fan.manual {
+                fan.duty = auto_duty(self.temperature);
+            }
+            let failed =
+                config.fan_failures.iter().any(|&(idx, at)| idx == fan_idx && elapsed >= at);
+            let target_speed = if failed { 0.0 } else { f64::from(fan.duty) };
+
+            let lag = 1.0 - (-dt / config.fan_time_constant).exp();
+            fan.speed += (target_speed - fan.speed) * lag;
+        }
+
+        let fan_speed = if self.fans.is_empty() {
+            0.0
+        } else {
+            self.fans.iter().map(|fan| fan.speed).sum::<f64>() / self.fans.len() as f64
+        };
+
+        let conductance = config.passive_conductance + config.fan_conductance * fan_speed / 100.0;
+        let heat_flow = self.power - conductance * (self.temperature - ambient);
+        self.temperature += heat_flow / config.heat_capacity * dt;
+    }
+}
+
+impl SimulatedGpuConfig {
+    fn power_at(&self, elapsed: f64) -> f64 {
+        let elapsed = match self.load_period {
+            Some(period) if period > 0.0 => elapsed % period,
+            _ => elapsed,
+        };
+
+        self.load
+            .iter()
+            .take_while(|(start, _)| *start <= elapsed)
+            .last()
+            .map_or(0.0, |(_, power)| *power)
+    }
+
+    fn max_power(&self) -> f64 {
+        self.load.iter().map(|(_, power)| *power).fold(1.0, f64::max)
+    }
+
+    fn power_limit(&self) -> f64 {
+        self.power_limit.unwrap_or_else(|| self.max_power())
+    }
+}
+
+impl GpuBackend for SimulatedBackend {
+    fn device_count(&self) -> usize {
+        self.scenario.devices.len()
+    }
+
+    fn read_persistent_params(&self, device_idx: usize) -> Result<PersistentGpuParams> {
+        let config = self.config(device_idx)?;
+
+        Ok(PersistentGpuParams {
+            sys_info: SysInfo {
+                cuda_version: CudaVersion { major: 0, minor: 0 },
+                driver_version: "simulated".to_string(),
+                cuda_capability: CudaComputeCapability { major: 0, minor: 0 },
+                nvml_version: "simulated".to_string(),
+            },
+            index: device_idx,
+            uuid: format!("GPU-SIMULATED-{device_idx}"),
+            pci_bus_id: format!("00000000:{device_idx:02X}:00.0"),
+            device_name: config.name.clone(),
+            architecture: GpuArchitecture::Unknown,
+            num_cores: 0,
+            num_fans: config.num_fans,
+            max_pcie_link: PCIeLink { gen: 4, width: 16, speed: 16_000 * 1_000_000 },
+            temp_thresholds: config.temp_thresholds.clone(),
+            minmax_fan_speeds: config.minmax_fan_speeds,
+        })
+    }
+
+    fn read_runtime_params(&self, device_idx: usize, num_fans: usize) -> Result<RuntimeGpuParams> {
+        let config = self.config(device_idx)?;
+        let state = self.device_state(device_idx)?;
+        let gpu =
+            state.gpus.get(device_idx).with_context(|| format!("GPU {device_idx} not found"))?;
+
+        let load = gpu.power / config.max_power();
+        let clock = |idle: f64, max: f64| (idle + (max - idle) * load).round() as u32;
+
+        Ok(RuntimeGpuParams {
+            probe_time: Local::now(),
+            current_pcie_link: PCIeLink { gen: 4, width: 16, speed: 16_000 * 1_000_000 },
+            memory_info: GpuMemStats {
+                free: 7 * 1024 * 1024 * 1024,
+                total: 8 * 1024 * 1024 * 1024,
+                used: 1024 * 1024 * 1024,
+            },
+            power_usage: gpu.power,
+            power_limit: Some(config.power_limit()),
+            device_temperature: gpu.temperature.round() as u32,
+            // filled in by GpuManager
+            filtered_temperature: None,
+            memory_temperature: gpu.memory_temperature(config).map(|temp| temp.round() as u32),
+            fan_states: gpu
+                .fans
+                .iter()
+                .take(num_fans)
+                .enumerate()
+                .map(|(index, fan)| FanState {
+                    index,
+                    speed: fan.speed.round() as u32,
+                    duty: fan.duty,
+                    // filled in by GpuManager
+                    target_duty: None,
+                    control_policy: if fan.manual {
+                        FanControlPolicy::Manual
+                    } else {
+                        FanControlPolicy::Automatic
+                    },
+                })
+                .collect(),
+            clock_speeds: ClockSpeeds {
+                memory: clock(405.0, 7000.0),
+                graphics: clock(210.0, 2000.0),
+                video: clock(555.0, 1800.0),
+                streaming_multiprocessor: clock(210.0, 2000.0),
+            },
+        })
+    }
+
+    fn temperature(&self, device_idx: usize) -> Result<u32> {
+        let state = self.device_state(device_idx)?;
+        let gpu =
+            state.gpus.get(device_idx).with_context(|| format!("GPU {device_idx} not found"))?;
+
+        Ok(gpu.temperature.round() as u32)
+    }
+
+    fn read_input(&self, device_idx: usize, input: ControlInput) -> Result<u32> {
+        let config = self.config(device_idx)?;
+        let state = self.device_state(device_idx)?;
+        let gpu =
+            state.gpus.get(device_idx).with_context(|| format!("GPU {device_idx} not found"))?;
+
+        let value = match input {
+            ControlInput::CoreTemperature => gpu.temperature,
+            ControlInput::MemoryTemperature => gpu
+                .memory_temperature(config)
+                .with_context(|| format!("GPU {device_idx} does not report memory temperature"))?,
+            ControlInput::Power => gpu.power / config.power_limit() * 100.0,
+        };
+
+        Ok(value.round() as u32)
+    }
+
+    fn fan_speed(&self, device_idx: usize, fan_idx: usize) -> Result<u32> {
+        Ok(self.device_state(device_idx)?.fan(device_idx, fan_idx)?.speed.round() as u32)
+    }
+
+    fn fan_duty(&self, device_idx: usize, fan_idx: usize) -> Result<u32> {
+        Ok(self.device_state(device_idx)?.fan(device_idx, fan_idx)?.duty)
+    }
+
+    fn fan_control_policy(&self, device_idx: usize, fan_idx: usize) -> Result<FanControlPolicy> {
+        let mut state = self.device_state(device_idx)?;
+
+        Ok(if state.fan(device_idx, fan_idx)?.manual {
+            FanControlPolicy::Manual
+        } else {
+            FanControlPolicy::Automatic
+        })
+    }
+
+    fn set_fan_duty(&self, device_idx: usize, fan_idx: usize, duty: u32) -> Result<()> {
+        ensure!(duty <= 100, "Fan duty cannot be higher than 100%");
+
+        let mut state = self.device_state(device_idx)?;
+        let fan = state.fan(device_idx, fan_idx)?;
+        fan.duty = duty;
+        fan.manual = true;
+
+        Ok(())
+    }
+
+    fn set_default_fan_policy(&self, device_idx: usize, fan_idx: usize) -> Result<()> {
+        self.device_state(device_idx)?.fan(device_idx, fan_idx)?.manual = false;
+
+        Ok(())
+    }
+
+    fn is_retryable(&self, err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| cause.is::<SimulatedOutage>())
+    }
+
+    fn reinit(&self) -> Result<()> {
+        // driver comes back only when all GPUs are reachable again
+        for device_idx in 0..self.device_count() {
+            drop(self.device_state(device_idx)?);
+        }
+        info!("Simulation initialised again");
+
+        Ok(())
+    }
+}
+
+/// Stands in for the errors of a GPU which fell off the bus
+#[derive(Debug)]
+struct SimulatedOutage {
+    device_idx: usize,
+}
+
+impl Display for SimulatedOutage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GPU {} is lost (simulated outage)", self.device_idx)
+    }
+}
+
+impl std::error::Error for SimulatedOutage {}
+
+/// Very rough approximation of what the driver does in automatic mode
+fn auto_duty(temperature: f64) -> u32 {
+    (30.0 + (temperature - 40.0) * 1.5).clamp(30.0, 100.0).round() as u32
+}
+
+fn default_time_scale() -> f64 {
+    1.0
+}
+
+fn default_temp_thresholds() -> GpuTemperatureThresholds {
+    GpuTemperatureThresholds { shutdown: 98, slowdown: 95, gpumax: 90 }
+}
+
+fn default_minmax_fan_speeds() -> MinMaxFanSpeeds {
+    MinMaxFanSpeeds { min: 30, max: 100 }
+}
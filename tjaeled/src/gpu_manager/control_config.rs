@@ -0,0 +1,451 @@
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{ensure, Context, Result};
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use serde_with::serde_as;
+use tjaele_types::{
+    validate_fan_curve, ControlInput, ControlMode, EmergencyReaction, PersistentGpuParams,
+    StallReaction,
+};
+use tracing::info;
+
+use super::fan_curve::sorted_anchor_points;
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+pub struct TjaeleControlConfig {
+    #[serde_as(as = "serde_with::DurationSecondsWithFrac<f64>")]
+    pub response_time: Duration,
+    pub hysteresis: u16,
+    /// How often policy and duty of controlled fans are read back
+    #[serde_as(as = "serde_with::DurationSecondsWithFrac<f64>")]
+    #[serde(default = "default_verify_interval")]
+    pub verify_interval: Duration,
+    /// Telemetry samples kept for each GPU, one is taken every control step
+    #[serde(default = "default_history_samples")]
+    pub history_samples: usize,
+    /// Telemetry is recorded to disk only when present
+    pub logging: Option<LoggingConfig>,
+    /// Smoothing of all inputs before they reach the controller
+    #[serde(default)]
+    pub filter: FilterConfig,
+    /// Limits how fast fan duty changes
+    #[serde(default)]
+    pub ramp: RampConfig,
+    #[serde(default)]
+    pub stall_detection: StallDetectionConfig,
+    #[serde(default)]
+    pub thermal_emergency: ThermalEmergencyConfig,
+    #[serde(default)]
+    pub mode: ControlMode,
+    /// Required in PID mode
+    pub pid: Option<PidConfig>,
+    /// Fan curve over core temperature used for all GPUs without their own curve
+    #[serde_as(as = "Vec<(_, _)>")]
+    pub fan_curve: FxHashMap<u8, u8>,
+    /// Points `fan_curve` was precomputed from, sorted by temperature
+    #[serde(skip)]
+    pub anchor_points: Vec<(u8, u8)>,
+    /// Additional inputs for all GPUs without their own, the highest duty wins
+    #[serde(default, rename = "input")]
+    pub inputs: Vec<InputControlConfig>,
+    /// Adjustments of the curve for individual fans of all GPUs without their own
+    #[serde(default, rename = "fan")]
+    pub fans: Vec<FanControlConfig>,
+    /// Fan curves assigned to specific GPUs
+    #[serde(default, rename = "device")]
+    pub devices: Vec<DeviceControlConfig>,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceControlConfig {
+    /// GPU UUID (eg. `GPU-8d5d...`) or PCI bus id (eg. `00000000:01:00.0`)
+    pub id: String,
+    #[serde_as(as = "Vec<(_, _)>")]
+    pub fan_curve: FxHashMap<u8, u8>,
+    #[serde(skip)]
+    pub anchor_points: Vec<(u8, u8)>,
+    /// Replaces the top-level mode for this GPU when present
+    pub mode: Option<ControlMode>,
+    /// Replaces the top-level PID config for this GPU when present
+    pub pid: Option<PidConfig>,
+    /// Replaces the top-level inputs for this GPU when present
+    #[serde(rename = "input")]
+    pub inputs: Option<Vec<InputControlConfig>>,
+    /// Replaces the top-level fan adjustments for this GPU when present
+    #[serde(rename = "fan")]
+    pub fans: Option<Vec<FanControlConfig>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FilterConfig {
+    #[default]
+    None,
+    /// Mean of the last samples
+    MovingAverage { samples: usize },
+    /// Each sample moves the value by `alpha` of the difference
+    Ema { alpha: f64 },
+    /// Highest of the last samples, so fans react to heat right away but drop late
+    WindowedMax { samples: usize },
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RampConfig {
+    /// Highest duty increase (% per second), unlimited if not set
+    pub up: Option<f64>,
+    /// Highest duty decrease (% per second), unlimited if not set
+    pub down: Option<f64>,
+    /// How long fans keep their duty after a lower one is requested
+    #[serde_as(as = "Option<serde_with::DurationSecondsWithFrac<f64>>")]
+    pub spin_down_delay: Option<Duration>,
+}
+
+/// Fan stalls when its speed stays below its duty by more than `max_gap` for the whole `window`
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StallDetectionConfig {
+    pub enabled: bool,
+    #[serde_as(as = "serde_with::DurationSecondsWithFrac<f64>")]
+    pub window: Duration,
+    /// Percentage points
+    pub max_gap: u8,
+    pub reaction: StallReaction,
+}
+
+impl Default for StallDetectionConfig {
+    fn default() -> Self {
+        StallDetectionConfig {
+            enabled: true,
+            window: Duration::from_secs(15),
+            max_gap: 40,
+            reaction: StallReaction::Alarm,
+        }
+    }
+}
+
+/// Emergency starts `margin` below the lower of slowdown and gpumax temperatures
+/// and ends once the temperature drops another `release` below that
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ThermalEmergencyConfig {
+    /// Celsius
+    pub margin: u32,
+    /// Celsius
+    pub release: u32,
+    pub reaction: EmergencyReaction,
+}
+
+impl Default for ThermalEmergencyConfig {
+    fn default() -> Self {
+        ThermalEmergencyConfig { margin: 5, release: 10, reaction: EmergencyReaction::MaxDuty }
+    }
+}
+
+/// Files are rotated when they get bigger than `max_file_size` or older than
+/// `max_file_age`, the oldest are removed when all files take more than `max_total_size`
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub directory: PathBuf,
+    /// Bytes
+    pub max_file_size: u64,
+    #[serde_as(as = "serde_with::DurationSecondsWithFrac<f64>")]
+    pub max_file_age: Duration,
+    /// Bytes
+    pub max_total_size: u64,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            directory: PathBuf::from("/var/lib/tjaele"),
+            max_file_size: 16 * 1024 * 1024,
+            max_file_age: Duration::from_secs(24 * 60 * 60),
+            max_total_size: 256 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PidConfig {
+    /// Value of the input the controller tries to hold
+    pub setpoint: f64,
+    #[serde(default = "default_pid_source")]
+    pub source: ControlInput,
+    /// Proportional gain (% of duty per unit of error)
+    pub kp: f64,
+    /// Integral gain (% of duty per unit of error per second)
+    #[serde(default)]
+    pub ki: f64,
+    /// Derivative gain (% of duty per unit of error change per second)
+    #[serde(default)]
+    pub kd: f64,
+    /// Output limits, always narrowed to the min/max fan speed of the GPU
+    #[serde(default)]
+    pub min_duty: u8,
+    #[serde(default = "default_max_duty")]
+    pub max_duty: u8,
+}
+
+/// Control input with its own curve, values of the curve are the input units
+/// (Celsius or percent of power limit) instead of core temperature
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+pub struct InputControlConfig {
+    pub source: ControlInput,
+    #[serde_as(as = "Vec<(_, _)>")]
+    pub fan_curve: FxHashMap<u8, u8>,
+}
+
+/// Duty of a fan is its curve duty (or GPU curve duty) multiplied by `scale`
+/// and shifted by `offset`, clamped to 0-100%
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+pub struct FanControlConfig {
+    /// Index of the fan on the GPU
+    pub index: usize,
+    /// Fan curve over core temperature used instead of the GPU curve
+    #[serde_as(as = "Option<Vec<(_, _)>>")]
+    pub fan_curve: Option<FxHashMap<u8, u8>>,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    /// Percentage points
+    #[serde(default)]
+    pub offset: i8,
+}
+
+impl TjaeleControlConfig {
+    pub(super) fn new_from_file<Q: AsRef<Path> + Debug>(path: Q) -> Result<Self> {
+        let cfg = std::fs::read_to_string(&path)?;
+        let cfg = Self::new_from_str(&cfg)?;
+
+        info!("Config loaded from {path:?}");
+
+        Ok(cfg)
+    }
+
+    pub fn new_from_str(cfg: &str) -> Result<Self> {
+        let cfg: Self = toml::from_str(cfg)?;
+        cfg.validate()
+    }
+
+    pub(super) fn validate(self) -> Result<Self> {
+        ensure!(
+            self.hysteresis > 0 && self.hysteresis <= 5,
+            "Hysteresis must be between 1C and 5C"
+        );
+        ensure!(
+            self.response_time.as_secs_f64() >= 0.25,
+            "Response time must be at least than 0.25 seconds"
+        );
+        ensure!(self.history_samples > 0, "History must keep at least one sample");
+
+        if let Some(logging) = &self.logging {
+            ensure!(logging.max_file_size > 0, "Telemetry file size must be positive");
+            ensure!(
+                logging.max_total_size >= logging.max_file_size,
+                "Total telemetry size cannot be lower than the size of one file"
+            );
+            ensure!(
+                logging.max_file_age >= Duration::from_secs(60),
+                "Telemetry files must be kept for at least 60 seconds"
+            );
+        }
+
+        ensure!(
+            [self.ramp.up, self.ramp.down]
+                .iter()
+                .flatten()
+                .all(|rate| rate.is_finite() && *rate > 0.0),
+            "Ramp rates must be positive"
+        );
+
+        match self.filter {
+            FilterConfig::None => {},
+            FilterConfig::MovingAverage { samples } | FilterConfig::WindowedMax { samples } => {
+                ensure!((1..=600).contains(&samples), "Filter must use between 1 and 600 samples");
+            },
+            FilterConfig::Ema { alpha } => {
+                ensure!(alpha > 0.0 && alpha <= 1.0, "Filter alpha must be between 0.0 and 1.0");
+            },
+        }
+
+        ensure!(
+            self.stall_detection.window >= Duration::from_secs(1),
+            "Stall detection window must be at least 1 second"
+        );
+        ensure!(
+            (1..=100).contains(&self.stall_detection.max_gap),
+            "Stall detection gap must be between 1% and 100%"
+        );
+
+        ensure!(
+            self.thermal_emergency.margin <= 30,
+            "Thermal emergency margin cannot be more than 30C"
+        );
+        ensure!(
+            (1..=40).contains(&self.thermal_emergency.release),
+            "Thermal emergency release must be between 1C and 40C"
+        );
+
+        validate_anchor_points(&self.fan_curve)?;
+        validate_mode(self.mode, self.pid.as_ref())?;
+        validate_input_configs(&self.inputs)?;
+        validate_fan_configs(&self.fans)?;
+
+        for device_config in &self.devices {
+            validate_anchor_points(&device_config.fan_curve)
+                .with_context(|| format!("Invalid fan curve for GPU {}", device_config.id))?;
+            validate_mode(
+                device_config.mode.unwrap_or(self.mode),
+                device_config.pid.as_ref().or(self.pid.as_ref()),
+            )
+            .with_context(|| format!("Invalid mode config for GPU {}", device_config.id))?;
+            validate_input_configs(device_config.inputs.as_deref().unwrap_or_default())
+                .with_context(|| format!("Invalid input config for GPU {}", device_config.id))?;
+            validate_fan_configs(device_config.fans.as_deref().unwrap_or_default())
+                .with_context(|| format!("Invalid fan config for GPU {}", device_config.id))?;
+        }
+
+        Ok(self)
+    }
+
+    /// Returns config assigned to given GPU, if any
+    pub fn device_config(&self, params: &PersistentGpuParams) -> Option<&DeviceControlConfig> {
+        self.devices.iter().find(|device_config| device_config.matches(params))
+    }
+
+    /// Returns fan curve assigned to given GPU or the default one
+    pub fn fan_curve_for(&self, params: &PersistentGpuParams) -> &FxHashMap<u8, u8> {
+        self.device_config(params).map_or(&self.fan_curve, |device_config| &device_config.fan_curve)
+    }
+
+    /// Returns anchor points of the fan curve assigned to given GPU or of the default one
+    pub fn anchor_points_for(&self, params: &PersistentGpuParams) -> &[(u8, u8)] {
+        self.device_config(params)
+            .map_or(&self.anchor_points, |device_config| &device_config.anchor_points)
+    }
+
+    /// Returns control mode assigned to given GPU or the default one
+    pub fn mode_for(&self, params: &PersistentGpuParams) -> ControlMode {
+        self.device_config(params).and_then(|device_config| device_config.mode).unwrap_or(self.mode)
+    }
+
+    /// Returns PID config assigned to given GPU or the default one
+    pub fn pid_for(&self, params: &PersistentGpuParams) -> Option<&PidConfig> {
+        self.device_config(params)
+            .and_then(|device_config| device_config.pid.as_ref())
+            .or(self.pid.as_ref())
+    }
+
+    /// Returns additional inputs assigned to given GPU or the default ones
+    pub fn inputs_for(&self, params: &PersistentGpuParams) -> &[InputControlConfig] {
+        self.device_config(params)
+            .and_then(|device_config| device_config.inputs.as_deref())
+            .unwrap_or(&self.inputs)
+    }
+
+    /// Returns fan adjustments assigned to given GPU or the default ones
+    pub fn fan_configs_for(&self, params: &PersistentGpuParams) -> &[FanControlConfig] {
+        self.device_config(params)
+            .and_then(|device_config| device_config.fans.as_deref())
+            .unwrap_or(&self.fans)
+    }
+}
+
+impl DeviceControlConfig {
+    pub fn matches(&self, params: &PersistentGpuParams) -> bool {
+        Self::id_matches(&self.id, params)
+    }
+
+    pub fn id_matches(id: &str, params: &PersistentGpuParams) -> bool {
+        id.eq_ignore_ascii_case(&params.uuid) || id.eq_ignore_ascii_case(&params.pci_bus_id)
+    }
+}
+
+fn validate_mode(mode: ControlMode, pid: Option<&PidConfig>) -> Result<()> {
+    let Some(pid) = pid else {
+        ensure!(mode != ControlMode::Pid, "PID mode requires PID config");
+        return Ok(());
+    };
+
+    ensure!(pid.setpoint.is_finite() && pid.setpoint > 0.0, "PID setpoint must be positive");
+    ensure!(
+        [pid.kp, pid.ki, pid.kd].iter().all(|gain| gain.is_finite() && *gain >= 0.0),
+        "PID gains cannot be negative"
+    );
+    ensure!(pid.min_duty <= pid.max_duty, "PID min duty cannot be higher than max duty");
+    ensure!(pid.max_duty <= 100, "Fan duty cannot be higher than 100%");
+
+    Ok(())
+}
+
+fn validate_input_configs(input_configs: &[InputControlConfig]) -> Result<()> {
+    for input_config in input_configs {
+        validate_anchor_points(&input_config.fan_curve)
+            .with_context(|| format!("Invalid fan curve for input {}", input_config.source))?;
+    }
+
+    Ok(())
+}
+
+fn validate_fan_configs(fan_configs: &[FanControlConfig]) -> Result<()> {
+    for (i, fan_config) in fan_configs.iter().enumerate() {
+        ensure!(
+            fan_configs[..i].iter().all(|other| other.index != fan_config.index),
+            "Fan {} is configured more than once",
+            fan_config.index
+        );
+        ensure!(
+            fan_config.scale >= 0.0 && fan_config.scale <= 10.0,
+            "Scale of fan {} must be between 0.0 and 10.0",
+            fan_config.index
+        );
+        ensure!(
+            (-100..=100).contains(&fan_config.offset),
+            "Offset of fan {} must be between -100 and 100",
+            fan_config.index
+        );
+
+        if let Some(fan_curve) = &fan_config.fan_curve {
+            validate_anchor_points(fan_curve)
+                .with_context(|| format!("Invalid fan curve for fan {}", fan_config.index))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) fn validate_anchor_points(fan_curve: &FxHashMap<u8, u8>) -> Result<()> {
+    Ok(validate_fan_curve(&sorted_anchor_points(fan_curve))?)
+}
+
+fn default_history_samples() -> usize {
+    3600
+}
+
+fn default_verify_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_pid_source() -> ControlInput {
+    ControlInput::CoreTemperature
+}
+
+fn default_max_duty() -> u8 {
+    100
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
@@ -0,0 +1,155 @@
+#![allow(clippy::cast_sign_loss)]
+
+use std::fmt::Debug;
+
+mod curve;
+mod pid;
+
+use anyhow::{ensure, Context, Result};
+use tjaele_types::{ControlInput, ControlMode, StallReaction};
+use tracing::{trace, warn};
+
+use super::GpuManager;
+use curve::CurveController;
+use pid::PidController;
+
+/// Strategy computing the duty of all curve controlled fans of one GPU.
+///
+/// Controllers keep their state between steps and are recreated whenever
+/// the control config changes.
+pub trait Controller: Debug + Send {
+    /// Inputs the controller needs, in the order their readings are passed to it
+    fn inputs(&self) -> &[ControlInput];
+
+    /// Returns the new duty (0-100) for filtered readings of the inputs,
+    /// or `None` if the current one should be kept
+    fn target_duty(&mut self, device_idx: usize, readings: &[f64]) -> Result<Option<u8>>;
+}
+
+impl GpuManager {
+    /// Creates controller for given GPU from the config in use
+    pub fn controller(&self, device_idx: usize) -> Box<dyn Controller> {
+        let persistent_params = &self.persistent_params[device_idx];
+        let control_config = self.control_config();
+
+        match (
+            control_config.mode_for(persistent_params),
+            control_config.pid_for(persistent_params),
+        ) {
+            (ControlMode::Pid, Some(pid_config)) => Box::new(PidController::new(
+                pid_config.clone(),
+                persistent_params.minmax_fan_speeds,
+            )),
+            (ControlMode::Pid, None) => {
+                // config validation does not let this happen
+                warn!("GPU {device_idx} has no PID config, falling back to curve control");
+                Box::new(CurveController::new(&control_config, persistent_params))
+            },
+            (ControlMode::Curve, _) => {
+                Box::new(CurveController::new(&control_config, persistent_params))
+            },
+        }
+    }
+
+    /// Moves fans not under override towards the duty from the controller
+    /// (adjusted for each fan), as fast as the ramp allows
+    pub fn set_duty_with_controller(
+        &self,
+        device_idx: usize,
+        controller: &mut dyn Controller,
+    ) -> Result<()> {
+        let persistent_params = &self.persistent_params[device_idx];
+
+        // fans with their own curve follow core temperature in every mode
+        let mut sources = vec![ControlInput::CoreTemperature];
+        for &source in controller.inputs() {
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+
+        let raw_readings = sources
+            .iter()
+            .map(|&source| {
+                self.backend
+                    .read_input(device_idx, source)
+                    .with_context(|| format!("Failed to read input {source}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let control_config = self.control_config();
+        let mut control_states = self.control_states();
+        let control_state = &mut control_states[device_idx];
+
+        let readings = sources
+            .iter()
+            .zip(raw_readings)
+            .map(|(&source, raw_reading)| {
+                let filter = control_state.input_filters.entry(source).or_default();
+                (source, filter.update(f64::from(raw_reading), &control_config.filter))
+            })
+            .collect::<Vec<_>>();
+        let reading_of = |source: ControlInput| {
+            readings.iter().find(|(s, _)| *s == source).map_or(0.0, |(_, reading)| *reading)
+        };
+
+        let controller_readings =
+            controller.inputs().iter().map(|&source| reading_of(source)).collect::<Vec<_>>();
+        let target_duty = controller.target_duty(device_idx, &controller_readings)?;
+        ensure!(
+            target_duty.is_none_or(|duty| duty <= 100),
+            "Fan duty failed sanity check - this should not happen"
+        );
+
+        if let Some(target_duty) = target_duty {
+            let temp = reading_of(ControlInput::CoreTemperature).round().clamp(0.0, 255.0) as u8;
+            let fan_configs = control_config.fan_configs_for(persistent_params);
+
+            control_state.requested_duties = (0..persistent_params.num_fans)
+                .map(|fan_idx| {
+                    fan_configs
+                        .iter()
+                        .find(|fan_config| fan_config.index == fan_idx)
+                        .map_or(Ok(target_duty), |fan_config| fan_config.duty(target_duty, temp))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            trace!("GPU {device_idx} fan duties requested {:?}%", control_state.requested_duties);
+        }
+
+        let mut fan_duties =
+            control_state.duty_ramp.step(&control_state.requested_duties, &control_config.ramp);
+        let thermal_emergency = control_config.thermal_emergency.clone();
+        drop(control_config);
+
+        if self.thermal_guard(device_idx, control_state, &thermal_emergency)? {
+            return Ok(());
+        }
+
+        match control_state.stall_reaction {
+            None | Some(StallReaction::Alarm) => {},
+            Some(StallReaction::MaxOtherFans) => {
+                fan_duties = vec![100; persistent_params.num_fans];
+                for fan_idx in 0..persistent_params.num_fans {
+                    control_state.duty_ramp.hold(fan_idx, 100);
+                }
+            },
+            Some(StallReaction::Automatic) => return Ok(()),
+        }
+
+        for (fan_idx, &fan_duty) in fan_duties.iter().enumerate() {
+            let overridden = control_state.stall_reaction.is_none()
+                && control_state.duty_override.as_ref().is_some_and(|o| o.controls_fan(fan_idx));
+
+            if overridden || control_state.target_duties[fan_idx] == Some(fan_duty) {
+                continue;
+            }
+
+            self.set_fan_duty(device_idx, control_state, fan_idx, fan_duty)?;
+
+            trace!("GPU {device_idx} fan {fan_idx} duty changed to {fan_duty}%");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+#![allow(clippy::cast_sign_loss)]
+
+use anyhow::Result;
+use rustc_hash::FxHashMap;
+use tjaele_types::{ControlInput, PersistentGpuParams};
+use tracing::trace;
+
+use super::Controller;
+use crate::gpu_manager::{
+    control_config::InputControlConfig, fan_curve::curve_duty, TjaeleControlConfig,
+};
+
+/// Looks the duty up from precomputed curves, the highest duty of all inputs wins.
+///
+/// Duty changes only when any input moves out of the hysteresis band
+/// around its value from the last change.
+#[derive(Debug)]
+pub(super) struct CurveController {
+    /// Over core temperature
+    fan_curve: FxHashMap<u8, u8>,
+    inputs: Vec<InputControlConfig>,
+    /// Core temperature followed by sources of `inputs`
+    sources: Vec<ControlInput>,
+    hysteresis: f64,
+    /// Empty before the first step
+    previous_readings: Vec<f64>,
+}
+
+impl CurveController {
+    pub(super) fn new(control_config: &TjaeleControlConfig, params: &PersistentGpuParams) -> Self {
+        let inputs = control_config.inputs_for(params).to_vec();
+        let sources = std::iter::once(ControlInput::CoreTemperature)
+            .chain(inputs.iter().map(|input| input.source))
+            .collect();
+
+        CurveController {
+            fan_curve: control_config.fan_curve_for(params).clone(),
+            inputs,
+            sources,
+            hysteresis: f64::from(control_config.hysteresis),
+            previous_readings: Vec::new(),
+        }
+    }
+}
+
+impl Controller for CurveController {
+    fn inputs(&self) -> &[ControlInput] {
+        &self.sources
+    }
+
+    fn target_duty(&mut self, device_idx: usize, readings: &[f64]) -> Result<Option<u8>> {
+        let within_hysteresis = readings.len() == self.previous_readings.len()
+            && readings
+                .iter()
+                .zip(&self.previous_readings)
+                .all(|(reading, previous)| (reading - previous).abs() <= self.hysteresis);
+
+        if within_hysteresis {
+            trace!(
+                "GPU {device_idx} fan duty not changed - inputs within hysteresis ({readings:?})"
+            );
+            return Ok(None);
+        }
+
+        // curves are flat beyond their last point, so saturating is fine
+        let curve_point = |reading: f64| reading.round().clamp(0.0, 255.0) as u8;
+
+        let mut target_duty = curve_duty(&self.fan_curve, curve_point(readings[0]))?;
+
+        for (input, &reading) in self.inputs.iter().zip(&readings[1..]) {
+            target_duty = target_duty.max(curve_duty(&input.fan_curve, curve_point(reading))?);
+        }
+
+        trace!("GPU {device_idx} curve duty {target_duty}%, inputs ({readings:?})");
+        self.previous_readings = readings.to_vec();
+
+        Ok(Some(target_duty))
+    }
+}
@@ -0,0 +1,167 @@
+#![allow(clippy::cast_sign_loss)]
+
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use tjaele_types::{ControlInput, MinMaxFanSpeeds};
+use tracing::trace;
+
+use super::Controller;
+use crate::gpu_manager::control_config::PidConfig;
+
+/// Adjusts the duty to hold the input at the setpoint.
+///
+/// The integral term stops accumulating while the output is saturated
+/// in the direction of the error, so it does not wind up when fans
+/// cannot go any faster (or slower).
+#[derive(Debug)]
+pub(super) struct PidController {
+    config: PidConfig,
+    min_duty: f64,
+    max_duty: f64,
+    integral: f64,
+    /// Time and value of the previous reading
+    previous: Option<(Instant, f64)>,
+}
+
+impl PidController {
+    pub(super) fn new(config: PidConfig, minmax_fan_speeds: MinMaxFanSpeeds) -> Self {
+        let min_duty = f64::from(config.min_duty).max(f64::from(minmax_fan_speeds.min));
+        let max_duty = f64::from(config.max_duty).min(f64::from(minmax_fan_speeds.max));
+        // limits of the GPU win if they do not overlap with configured ones
+        let max_duty = max_duty.max(min_duty);
+
+        PidController {
+            config,
+            min_duty,
+            max_duty,
+            // fans start at minimum and the integral term ramps them up as needed
+            integral: min_duty,
+            previous: None,
+        }
+    }
+
+    /// Duty for `reading` taken at `now`
+    fn update(&mut self, device_idx: usize, reading: f64, now: Instant) -> f64 {
+        // positive when too hot, so more error means more fan
+        let error = reading - self.config.setpoint;
+
+        let (dt, derivative) = match self.previous {
+            Some((time, previous)) => {
+                let dt = (now - time).as_secs_f64();
+                let derivative = if dt > 0.0 { (reading - previous) / dt } else { 0.0 };
+                (dt, derivative)
+            },
+            None => (0.0, 0.0),
+        };
+        self.previous = Some((now, reading));
+
+        let proportional = self.config.kp * error;
+        let derivative = self.config.kd * derivative;
+        let integral = self.integral + self.config.ki * error * dt;
+
+        let output = proportional + integral + derivative;
+        let winding_up =
+            (output > self.max_duty && error > 0.0) || (output < self.min_duty && error < 0.0);
+
+        if !winding_up {
+            self.integral = integral.clamp(self.min_duty, self.max_duty);
+        }
+
+        let duty = (proportional + self.integral + derivative).clamp(self.min_duty, self.max_duty);
+
+        trace!(
+            "GPU {device_idx} PID duty {duty:.1}%, {} {reading} (P {proportional:.1}, I {:.1}, D \
+             {derivative:.1})",
+            self.config.source,
+            self.integral
+        );
+
+        duty
+    }
+}
+
+impl Controller for PidController {
+    fn inputs(&self) -> &[ControlInput] {
+        std::slice::from_ref(&self.config.source)
+    }
+
+    fn target_duty(&mut self, device_idx: usize, readings: &[f64]) -> Result<Option<u8>> {
+        let reading = *readings.first().context("PID input reading missing")?;
+        let duty = self.update(device_idx, reading, Instant::now());
+
+        Ok(Some(duty.round() as u8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use tjaele_types::{ControlInput, MinMaxFanSpeeds};
+
+    use super::PidController;
+    use crate::gpu_manager::control_config::PidConfig;
+
+    fn controller(kp: f64, ki: f64, minmax_fan_speeds: MinMaxFanSpeeds) -> PidController {
+        let config = PidConfig {
+            setpoint: 60.0,
+            source: ControlInput::CoreTemperature,
+            kp,
+            ki,
+            kd: 0.0,
+            min_duty: 30,
+            max_duty: 100,
+        };
+
+        PidController::new(config, minmax_fan_speeds)
+    }
+
+    /// Duties for readings taken one second apart, and a second after the previous ones
+    fn duties(controller: &mut PidController, readings: &[f64]) -> Vec<f64> {
+        readings
+            .iter()
+            .map(|&reading| {
+                let now = controller
+                    .previous
+                    .map_or_else(Instant::now, |(time, _)| time + Duration::from_secs(1));
+                controller.update(0, reading, now)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn integral_does_not_wind_up_while_saturated() {
+        let mut controller = controller(2.0, 1.0, MinMaxFanSpeeds { min: 0, max: 100 });
+
+        // P is 40 above the setpoint, I starts at min duty and grows by 20 each second
+        // until the output saturates, then stays put instead of growing towards 130
+        let hot = duties(&mut controller, &[80.0, 80.0, 80.0, 80.0]);
+        assert_eq!(hot, [70.0, 90.0, 90.0, 90.0]);
+
+        // below the setpoint the duty drops right away, from the integral held at 50
+        let cooled = duties(&mut controller, &[55.0]);
+        assert_eq!(cooled, [35.0]);
+    }
+
+    #[test]
+    fn integral_does_not_wind_down_below_min_duty() {
+        let mut controller = controller(2.0, 1.0, MinMaxFanSpeeds { min: 0, max: 100 });
+        duties(&mut controller, &[80.0, 80.0]);
+
+        // output wants to go below min duty, so the integral stays at 50
+        let cold = duties(&mut controller, &[40.0, 40.0, 40.0]);
+        assert_eq!(cold, [30.0, 30.0, 30.0]);
+
+        let at_setpoint = duties(&mut controller, &[60.0]);
+        assert_eq!(at_setpoint, [50.0]);
+    }
+
+    #[test]
+    fn clamps_duty_to_fan_speed_limits_of_gpu() {
+        let mut controller = controller(10.0, 0.0, MinMaxFanSpeeds { min: 40, max: 80 });
+
+        assert_eq!(duties(&mut controller, &[90.0]), [80.0]);
+        assert_eq!(duties(&mut controller, &[20.0]), [40.0]);
+    }
+}
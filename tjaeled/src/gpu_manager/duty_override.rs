@@ -0,0 +1,147 @@
+use std::time::Instant;
+
+use anyhow::{ensure, Context, Result};
+use chrono::{DateTime, Local, TimeDelta};
+use tjaele_types::{ActiveDutyOverride, DutyOverrideRequest};
+use tracing::{info, warn};
+
+use super::{thermal_emergency::temperature_limit, DeviceControlConfig, GpuManager};
+
+/// Override is dropped when GPU gets this close to its temperature limit (in C)
+const LIMIT_SAFETY_MARGIN: u32 = 10;
+
+#[derive(Debug, Clone)]
+pub(super) struct DutyOverride {
+    duty: u8,
+    fans: Vec<usize>,
+    expires: Option<(Instant, DateTime<Local>)>,
+}
+
+impl DutyOverride {
+    pub(super) fn to_active(&self) -> ActiveDutyOverride {
+        ActiveDutyOverride {
+            duty: self.duty,
+            fans: self.fans.clone(),
+            expires: self.expires.map(|(_, expires)| expires),
+        }
+    }
+
+    pub(super) fn controls_fan(&self, fan_idx: usize) -> bool {
+        self.fans.contains(&fan_idx)
+    }
+}
+
+impl GpuManager {
+    /// Pins fans at fixed duty until the override expires or is cleared
+    pub fn set_duty_override(&self, request: DutyOverrideRequest) -> Result<()> {
+        ensure!(request.duty <= 100, "Fan duty cannot be higher than 100%");
+        ensure!(
+            request.duration.is_none_or(|duration| !duration.is_zero()),
+            "Override duration must be positive"
+        );
+
+        let devices = match &request.device {
+            None => (0..self.device_count()).collect::<Vec<_>>(),
+            Some(id) => vec![self
+                .persistent_params
+                .iter()
+                .position(|params| DeviceControlConfig::id_matches(id, params))
+                .with_context(|| format!("GPU {id} not found"))?],
+        };
+
+        let expires = request
+            .duration
+            .map(|duration| -> Result<_> {
+                let expires =
+                    Instant::now().checked_add(duration).context("Override duration too long")?;
+                let expires_wall = Local::now() + TimeDelta::from_std(duration)?;
+                Ok((expires, expires_wall))
+            })
+            .transpose()?;
+
+        let new_overrides = devices
+            .iter()
+            .map(|&device_idx| -> Result<_> {
+                let num_fans = self.persistent_params[device_idx].num_fans;
+                let fans = request.fans.clone().unwrap_or_else(|| (0..num_fans).collect());
+
+                ensure!(!fans.is_empty(), "At least one fan must be overridden");
+                for &fan_idx in &fans {
+                    ensure!(fan_idx < num_fans, "GPU {device_idx} does not have fan {fan_idx}");
+                }
+
+                Ok((device_idx, DutyOverride { duty: request.duty, fans, expires }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut control_states = self.control_states();
+        for (device_idx, duty_override) in new_overrides {
+            control_states[device_idx].duty_override = Some(duty_override);
+        }
+        drop(control_states);
+        // fans no longer overridden must get their curve duty back
+        self.bump_control_generation();
+
+        info!("Fan duty of GPUs {devices:?} fixed at {}%", request.duty);
+
+        Ok(())
+    }
+
+    /// Returns all fans to curve control
+    pub fn clear_duty_overrides(&self) {
+        for control_state in self.control_states().iter_mut() {
+            control_state.duty_override = None;
+        }
+        self.bump_control_generation();
+
+        info!("Fan duty overrides cleared");
+    }
+
+    /// Sets the overridden duty or drops the override if it expired
+    /// or the GPU is getting too hot for a fixed duty
+    pub fn apply_duty_override(&self, device_idx: usize) -> Result<()> {
+        let thermal_emergency = self.control_config().thermal_emergency.clone();
+        let mut control_states = self.control_states();
+        let control_state = &mut control_states[device_idx];
+        let Some(duty_override) = &control_state.duty_override else {
+            return Ok(());
+        };
+
+        if control_state.stall_reaction.is_some() {
+            return Ok(());
+        }
+
+        if duty_override.expires.is_some_and(|(expires, _)| expires <= Instant::now()) {
+            info!("GPU {device_idx} fan duty override expired, returning to curve control");
+            control_state.duty_override = None;
+            self.bump_control_generation();
+            return Ok(());
+        }
+
+        let temp = self.backend.temperature(device_idx)?;
+        let limit = temperature_limit(&self.persistent_params[device_idx].temp_thresholds);
+
+        if let Some(limit) = limit.filter(|&limit| temp + LIMIT_SAFETY_MARGIN >= limit) {
+            warn!(
+                "GPU {device_idx} temperature ({temp}C) is approaching its limit ({limit}C), \
+                 returning to curve control"
+            );
+            control_state.duty_override = None;
+            self.bump_control_generation();
+            return Ok(());
+        }
+
+        let (duty, fans) = (duty_override.duty, duty_override.fans.clone());
+        if self.thermal_guard(device_idx, control_state, &thermal_emergency)? {
+            return Ok(());
+        }
+
+        for fan_idx in fans {
+            self.set_fan_duty(device_idx, control_state, fan_idx, duty)?;
+            // curve control continues from the overridden duty
+            control_state.duty_ramp.hold(fan_idx, duty);
+        }
+
+        Ok(())
+    }
+}
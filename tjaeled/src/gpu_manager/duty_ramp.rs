@@ -0,0 +1,154 @@
+#![allow(clippy::cast_sign_loss)]
+
+use std::time::Instant;
+
+use super::control_config::RampConfig;
+
+/// Moves duty of each fan towards the requested one no faster than the ramp allows
+#[derive(Debug, Clone, Default)]
+pub(super) struct DutyRamp {
+    fans: Vec<FanRamp>,
+    last_step: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct FanRamp {
+    /// Fractional, so slow ramps are not lost to rounding
+    duty: Option<f64>,
+    /// Since when lower duty is requested without a break
+    lower_since: Option<Instant>,
+}
+
+impl DutyRamp {
+    pub(super) fn new(num_fans: usize) -> Self {
+        DutyRamp { fans: vec![FanRamp::default(); num_fans], last_step: None }
+    }
+
+    /// Returns duty each fan should be set to now
+    pub(super) fn step(&mut self, requested: &[u8], config: &RampConfig) -> Vec<u8> {
+        self.step_at(requested, config, Instant::now())
+    }
+
+    fn step_at(&mut self, requested: &[u8], config: &RampConfig, now: Instant) -> Vec<u8> {
+        let dt = self.last_step.map_or(0.0, |last_step| (now - last_step).as_secs_f64());
+        self.last_step = Some(now);
+
+        self.fans
+            .iter_mut()
+            .zip(requested)
+            .map(|(fan, &requested)| fan.step(f64::from(requested), config, dt, now))
+            .collect()
+    }
+
+    /// Continues from given duty, when the fan was set outside of the ramp
+    pub(super) fn hold(&mut self, fan_idx: usize, duty: u8) {
+        if let Some(fan) = self.fans.get_mut(fan_idx) {
+            *fan = FanRamp { duty: Some(f64::from(duty)), lower_since: None };
+        }
+    }
+}
+
+impl FanRamp {
+    fn step(&mut self, requested: f64, config: &RampConfig, dt: f64, now: Instant) -> u8 {
+        let duty = match self.duty {
+            // nothing to ramp from
+            None => requested,
+            Some(duty) if requested > duty => {
+                self.lower_since = None;
+                config.up.map_or(requested, |rate| (duty + rate * dt).min(requested))
+            },
+            Some(duty) if requested < duty => {
+                let lower_since = *self.lower_since.get_or_insert(now);
+
+                if config.spin_down_delay.is_some_and(|delay| now - lower_since < delay) {
+                    duty
+                } else {
+                    config.down.map_or(requested, |rate| (duty - rate * dt).max(requested))
+                }
+            },
+            Some(duty) => {
+                self.lower_since = None;
+                duty
+            },
+        };
+
+        self.duty = Some(duty);
+        duty.round() as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::DutyRamp;
+    use crate::gpu_manager::control_config::RampConfig;
+
+    /// Up twice as fast as down, with fans kept up for 10s
+    fn config() -> RampConfig {
+        RampConfig {
+            up: Some(10.0),
+            down: Some(5.0),
+            spin_down_delay: Some(Duration::from_secs(10)),
+        }
+    }
+
+    /// Duty of a single fan requested at each of `seconds` after `start`
+    fn steps(ramp: &mut DutyRamp, requested: u8, start: Instant, seconds: &[u64]) -> Vec<u8> {
+        seconds
+            .iter()
+            .map(|&second| {
+                let now = start + Duration::from_secs(second);
+                ramp.step_at(&[requested], &config(), now)[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn starts_at_requested_duty() {
+        let mut ramp = DutyRamp::new(1);
+
+        assert_eq!(steps(&mut ramp, 70, Instant::now(), &[0]), [70]);
+    }
+
+    #[test]
+    fn steps_up_at_up_rate() {
+        let mut ramp = DutyRamp::new(1);
+        ramp.hold(0, 30);
+
+        assert_eq!(steps(&mut ramp, 80, Instant::now(), &[0, 2, 4, 10]), [30, 50, 70, 80]);
+    }
+
+    #[test]
+    fn steps_down_at_down_rate_after_delay() {
+        let mut ramp = DutyRamp::new(1);
+        ramp.hold(0, 80);
+
+        // kept up for the delay, then down by 5% each second until the requested duty
+        let duties = steps(&mut ramp, 40, Instant::now(), &[0, 5, 9, 10, 12, 20]);
+        assert_eq!(duties, [80, 80, 80, 75, 65, 40]);
+    }
+
+    #[test]
+    fn delay_starts_over_when_duty_goes_up_in_between() {
+        let mut ramp = DutyRamp::new(1);
+        ramp.hold(0, 80);
+        let start = Instant::now();
+
+        assert_eq!(steps(&mut ramp, 40, start, &[0, 9]), [80, 80]);
+        assert_eq!(steps(&mut ramp, 90, start, &[10]), [90]);
+        assert_eq!(steps(&mut ramp, 40, start, &[11, 20, 21]), [90, 90, 85]);
+    }
+
+    #[test]
+    fn hold_continues_from_given_duty() {
+        let mut ramp = DutyRamp::new(2);
+        let start = Instant::now();
+        ramp.step_at(&[40, 40], &config(), start);
+
+        // override set the first fan to 100% in the meantime
+        ramp.hold(0, 100);
+        let duties = ramp.step_at(&[40, 40], &config(), start + Duration::from_secs(1));
+        assert_eq!(duties, [100, 40]);
+    }
+}
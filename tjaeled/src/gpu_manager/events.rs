@@ -0,0 +1,40 @@
+use tjaele_types::DaemonEvent;
+use tokio::sync::broadcast;
+
+use super::GpuManager;
+
+/// Events buffered for each subscriber, the slow ones miss the oldest
+pub(super) const EVENT_CAPACITY: usize = 256;
+
+impl GpuManager {
+    pub fn subscribe(&self) -> broadcast::Receiver<DaemonEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn publish(&self, event: DaemonEvent) {
+        // nobody listening is not an error
+        let _ = self.events.send(event);
+    }
+
+    /// Publishes state of the GPU with runtime params read last,
+    /// so it has to run right after the history is recorded
+    pub fn publish_state(&self, device_idx: usize) {
+        if self.events.receiver_count() == 0 {
+            return;
+        }
+
+        let control_config = self.control_config();
+        let control_states = self.control_states();
+        let control_state = &control_states[device_idx];
+        let state = self.device_state(
+            device_idx,
+            control_state.last_runtime.clone(),
+            &control_config,
+            control_state,
+        );
+        drop(control_states);
+        drop(control_config);
+
+        self.publish(DaemonEvent::State(Box::new(state)));
+    }
+}
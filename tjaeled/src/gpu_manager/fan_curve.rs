@@ -10,50 +10,9 @@ use std::{
     hash::Hash,
 };
 
-use super::{GpuManager, TjaeleControlConfig};
-use crate::gpu_manager::intermediate_bindings::AdditionalNvmlFunctionality;
+use super::{control_config::FanControlConfig, TjaeleControlConfig};
 use anyhow::{anyhow, ensure, Context, Result};
-use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
 use rustc_hash::FxHashMap;
-use tracing::trace;
-
-impl GpuManager {
-    /// Returns temperature used for setting duty
-    pub fn set_duty_with_curve(&self, previous_temp: u32) -> Result<u32> {
-        let device = self.nvml_handle.borrow_device();
-
-        let new_temp =
-            device.temperature(TemperatureSensor::Gpu).context("Failed to read GPU temperature")?;
-
-        let hysteresis_range = previous_temp
-            .saturating_sub(u32::from(self.control_config.hysteresis))
-            ..=previous_temp.saturating_add(u32::from(self.control_config.hysteresis));
-
-        if hysteresis_range.contains(&new_temp) {
-            trace!("Fan duty not changed - temperature within hysteresis ({new_temp})C");
-            return Ok(previous_temp);
-        }
-
-        let temp_8bit =
-            u8::try_from(new_temp).context("Your device somehow is warmer than 255C")?;
-        let target_duty = *self
-            .control_config
-            .fan_curve
-            .get(&temp_8bit)
-            .context("Missing fan curve point - this should not happen")?;
-        ensure!(target_duty <= 100, "Fan duty failed sanity check - this should not happen");
-
-        for fan_idx in 0..self.persistent_params.num_fans {
-            device
-                .set_fan_speed(fan_idx as u32, u32::from(target_duty))
-                .context("Failed to set fan speed")?;
-        }
-
-        trace!("Fan duty changed to {target_duty}%, temperature ({new_temp})C");
-
-        Ok(new_temp)
-    }
-}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct FanCurvePoint {
@@ -69,61 +28,121 @@ impl From<(&u8, &u8)> for FanCurvePoint {
 
 impl TjaeleControlConfig {
     pub(super) fn precompute_fan_curve(mut self) -> Result<Self> {
-        let mut anchor_points = self.fan_curve.iter().map(FanCurvePoint::from).collect::<Vec<_>>();
-        anchor_points.sort_by_key(|pt| pt.temp);
-        let anchor_points = anchor_points; // remove mutability
+        self.anchor_points = sorted_anchor_points(&self.fan_curve);
+        self.fan_curve = precompute_fan_curve(&self.fan_curve)?;
 
-        // from 0 to first anchor we simply copy first duty (flat line)
-        for temp in 0..anchor_points[0].temp {
-            TryInsert::try_insert(&mut self.fan_curve, temp, anchor_points[0].duty)
-                .map_err(|_| anyhow!("Found curve point which should not yet be present"))?;
+        for device_config in &mut self.devices {
+            device_config.anchor_points = sorted_anchor_points(&device_config.fan_curve);
+            device_config.fan_curve = precompute_fan_curve(&device_config.fan_curve)
+                .with_context(|| format!("Invalid fan curve for GPU {}", device_config.id))?;
         }
 
-        // now we create a linear function between each pair and draw the curve
-        for i in 0..anchor_points.len() - 1 {
-            let lo_point = anchor_points[i];
-            let hi_point = anchor_points[i + 1];
+        let input_configs = self
+            .inputs
+            .iter_mut()
+            .chain(self.devices.iter_mut().flat_map(|d| d.inputs.iter_mut().flatten()));
 
-            ensure!(lo_point.duty <= hi_point.duty, "Fan duty must not decrease with temperature");
+        for input_config in input_configs {
+            input_config.fan_curve = precompute_fan_curve(&input_config.fan_curve)
+                .with_context(|| format!("Invalid fan curve for input {}", input_config.source))?;
+        }
 
-            let m = (f64::from(hi_point.duty) - f64::from(lo_point.duty))
-                / (f64::from(hi_point.temp) - f64::from(lo_point.temp));
-            let b = f64::from(lo_point.duty) - (m * f64::from(lo_point.temp));
+        let fan_configs = self
+            .fans
+            .iter_mut()
+            .chain(self.devices.iter_mut().flat_map(|d| d.fans.iter_mut().flatten()));
 
-            for temp in (lo_point.temp + 1)..hi_point.temp {
-                let duty = (m * f64::from(temp) + b).ceil() as u8;
-                TryInsert::try_insert(&mut self.fan_curve, temp, duty)
-                    .map_err(|_| anyhow!("Found curve point which should not yet be present"))?;
+        for fan_config in fan_configs {
+            if let Some(fan_curve) = &mut fan_config.fan_curve {
+                *fan_curve = precompute_fan_curve(fan_curve)
+                    .with_context(|| format!("Invalid fan curve for fan {}", fan_config.index))?;
             }
         }
 
-        let last_point = *anchor_points.last().context("Last curve point not found")?;
+        Ok(self)
+    }
+}
+
+impl FanControlConfig {
+    /// Applies the fan's own curve (if any), scale and offset
+    pub(super) fn duty(&self, curve_duty: u8, temp: u8) -> Result<u8> {
+        let curve_duty = match &self.fan_curve {
+            Some(fan_curve) => self::curve_duty(fan_curve, temp)?,
+            None => curve_duty,
+        };
+
+        let duty = f64::from(curve_duty) * self.scale + f64::from(self.offset);
+        Ok(duty.round().clamp(0.0, 100.0) as u8)
+    }
+}
+
+/// Anchor points are reported to clients, so they can edit the curve
+pub(super) fn sorted_anchor_points(anchors: &FxHashMap<u8, u8>) -> Vec<(u8, u8)> {
+    let mut anchor_points = anchors.iter().map(|(&temp, &duty)| (temp, duty)).collect::<Vec<_>>();
+    anchor_points.sort_unstable();
+    anchor_points
+}
+
+pub(super) fn curve_duty(fan_curve: &FxHashMap<u8, u8>, value: u8) -> Result<u8> {
+    fan_curve.get(&value).copied().context("Missing fan curve point - this should not happen")
+}
+
+pub(super) fn precompute_fan_curve(anchors: &FxHashMap<u8, u8>) -> Result<FxHashMap<u8, u8>> {
+    let mut fan_curve = anchors.clone();
+    let mut anchor_points = anchors.iter().map(FanCurvePoint::from).collect::<Vec<_>>();
+    anchor_points.sort_by_key(|pt| pt.temp);
+    let anchor_points = anchor_points; // remove mutability
+
+    // from 0 to first anchor we simply copy first duty (flat line)
+    for temp in 0..anchor_points[0].temp {
+        TryInsert::try_insert(&mut fan_curve, temp, anchor_points[0].duty)
+            .map_err(|_| anyhow!("Found curve point which should not yet be present"))?;
+    }
 
-        // from the last point to the end we again draw a flat line
-        for temp in (last_point.temp + 1)..=u8::MAX {
-            TryInsert::try_insert(&mut self.fan_curve, temp, last_point.duty)
+    // now we create a linear function between each pair and draw the curve
+    for i in 0..anchor_points.len() - 1 {
+        let lo_point = anchor_points[i];
+        let hi_point = anchor_points[i + 1];
+
+        ensure!(lo_point.duty <= hi_point.duty, "Fan duty must not decrease with temperature");
+
+        let m = (f64::from(hi_point.duty) - f64::from(lo_point.duty))
+            / (f64::from(hi_point.temp) - f64::from(lo_point.temp));
+        let b = f64::from(lo_point.duty) - (m * f64::from(lo_point.temp));
+
+        for temp in (lo_point.temp + 1)..hi_point.temp {
+            let duty = (m * f64::from(temp) + b).ceil() as u8;
+            TryInsert::try_insert(&mut fan_curve, temp, duty)
                 .map_err(|_| anyhow!("Found curve point which should not yet be present"))?;
         }
+    }
 
-        self.validate_fan_curve()?;
+    let last_point = *anchor_points.last().context("Last curve point not found")?;
 
-        Ok(self)
+    // from the last point to the end we again draw a flat line
+    for temp in (last_point.temp + 1)..=u8::MAX {
+        TryInsert::try_insert(&mut fan_curve, temp, last_point.duty)
+            .map_err(|_| anyhow!("Found curve point which should not yet be present"))?;
     }
 
-    fn validate_fan_curve(&self) -> Result<()> {
-        let mut curve_points = self.fan_curve.iter().map(FanCurvePoint::from).collect::<Vec<_>>();
-        curve_points.sort_by_key(|pt| pt.temp);
+    validate_fan_curve(&fan_curve)?;
 
-        for i in 0..curve_points.len() - 1 {
-            let lo_point = curve_points[i];
-            let hi_point = curve_points[i + 1];
+    Ok(fan_curve)
+}
 
-            ensure!(lo_point.duty <= hi_point.duty, "Generated fun curve is not valid (direction)");
-            ensure!(lo_point.duty <= 100, "Generated fun curve is not valid (fan duty)");
-            ensure!(hi_point.duty <= 100, "Generated fun curve is not valid (fan duty)");
-        }
-        Ok(())
+fn validate_fan_curve(fan_curve: &FxHashMap<u8, u8>) -> Result<()> {
+    let mut curve_points = fan_curve.iter().map(FanCurvePoint::from).collect::<Vec<_>>();
+    curve_points.sort_by_key(|pt| pt.temp);
+
+    for i in 0..curve_points.len() - 1 {
+        let lo_point = curve_points[i];
+        let hi_point = curve_points[i + 1];
+
+        ensure!(lo_point.duty <= hi_point.duty, "Generated fun curve is not valid (direction)");
+        ensure!(lo_point.duty <= 100, "Generated fun curve is not valid (fan duty)");
+        ensure!(hi_point.duty <= 100, "Generated fun curve is not valid (fan duty)");
     }
+    Ok(())
 }
 
 // direct copy from std, because try_insert not stabilised still
@@ -0,0 +1,149 @@
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use tjaele_types::{DaemonEvent, FanAlarm, StallReaction};
+use tracing::{error, info, warn};
+
+use super::GpuManager;
+
+/// Stall tracking of one fan
+#[derive(Debug, Clone, Default)]
+pub(super) struct FanStall {
+    /// Since when the fan lags behind its duty without a break
+    lagging_since: Option<Instant>,
+    /// Since when the fan with an alarm keeps up with its duty without a break
+    recovered_since: Option<Instant>,
+    pub(super) alarm: Option<FanAlarm>,
+}
+
+impl GpuManager {
+    /// Compares speed of each fan with the duty it was set to and reacts
+    /// to fans lagging behind for too long.
+    ///
+    /// Alarms stay raised until the fan keeps up with its duty for the whole window again
+    /// or they are acknowledged.
+    pub fn check_fan_stalls(&self, device_idx: usize) -> Result<()> {
+        let stall_config = self.control_config().stall_detection.clone();
+        if !stall_config.enabled {
+            return Ok(());
+        }
+
+        let speeds = (0..self.persistent_params[device_idx].num_fans)
+            .map(|fan_idx| self.backend.fan_speed(device_idx, fan_idx))
+            .collect::<Result<Vec<_>>>()
+            .context("Failed to read fan speeds")?;
+
+        let mut control_states = self.control_states();
+        let control_state = &mut control_states[device_idx];
+        let now = Instant::now();
+        let mut stalled = false;
+        let lags = |duty: u32, speed: u32| {
+            (speed == 0 && duty > 0) || duty.saturating_sub(speed) > u32::from(stall_config.max_gap)
+        };
+
+        for (fan_idx, &speed) in speeds.iter().enumerate() {
+            let fan_stall = &mut control_state.fan_stalls[fan_idx];
+            if fan_stall.alarm.is_some() {
+                // fans given back to the driver are compared with the duty it set
+                let duty = match control_state.target_duties[fan_idx] {
+                    Some(duty) => u32::from(duty),
+                    None => match self.backend.fan_duty(device_idx, fan_idx) {
+                        Ok(duty) => duty,
+                        // the rest of the control step must not depend on a stalled fan
+                        Err(err) => {
+                            warn!(
+                                "GPU {device_idx} fan {fan_idx} not known to have recovered, \
+                                 failed to read its duty: {err:#}"
+                            );
+                            fan_stall.recovered_since = None;
+                            continue;
+                        },
+                    },
+                };
+                // a fan which does not spin is not known to have recovered
+                if speed == 0 || lags(duty, speed) {
+                    fan_stall.recovered_since = None;
+                    continue;
+                }
+
+                let recovered_since = *fan_stall.recovered_since.get_or_insert(now);
+                if now - recovered_since >= stall_config.window {
+                    info!(
+                        "GPU {device_idx} fan {fan_idx} recovered - speed {speed}% at {duty}% duty"
+                    );
+                    *fan_stall = FanStall::default();
+                    self.publish(DaemonEvent::FanRecovered { device: device_idx, fan: fan_idx });
+                }
+                continue;
+            }
+
+            let lagging =
+                control_state.target_duties[fan_idx].filter(|&duty| lags(u32::from(duty), speed));
+
+            let Some(duty) = lagging else {
+                fan_stall.lagging_since = None;
+                continue;
+            };
+
+            let lagging_since = *fan_stall.lagging_since.get_or_insert(now);
+            if now - lagging_since < stall_config.window {
+                continue;
+            }
+
+            error!(
+                "GPU {device_idx} fan {fan_idx} stalled - speed {speed}% at {duty}% duty for \
+                 {:?}, reaction: {}",
+                stall_config.window, stall_config.reaction
+            );
+            let alarm = FanAlarm {
+                fan: fan_idx,
+                raised: Local::now(),
+                duty,
+                speed,
+                reaction: stall_config.reaction,
+            };
+            fan_stall.alarm = Some(alarm.clone());
+            self.publish(DaemonEvent::FanStalled { device: device_idx, alarm });
+            stalled = true;
+        }
+
+        if control_state.fan_stalls.iter().all(|fan_stall| fan_stall.alarm.is_none()) {
+            // reaction lasts only while some fan of the GPU is stalled
+            control_state.stall_reaction = None;
+        }
+
+        if !stalled || control_state.stall_reaction.is_some() {
+            return Ok(());
+        }
+
+        match stall_config.reaction {
+            StallReaction::Alarm => {},
+            StallReaction::MaxOtherFans => {
+                control_state.stall_reaction = Some(StallReaction::MaxOtherFans);
+            },
+            StallReaction::Automatic => {
+                control_state.stall_reaction = Some(StallReaction::Automatic);
+
+                for fan_idx in 0..speeds.len() {
+                    self.backend.set_default_fan_policy(device_idx, fan_idx)?;
+                    // duty has to be set again when control is taken back
+                    control_state.target_duties[fan_idx] = None;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Drops alarms of all fans and the reaction to them, returning fans to the controller
+    pub fn clear_fan_alarms(&self) {
+        for control_state in self.control_states().iter_mut() {
+            control_state.fan_stalls.fill(FanStall::default());
+            control_state.stall_reaction = None;
+        }
+        self.bump_control_generation();
+
+        info!("Fan alarms acknowledged");
+    }
+}
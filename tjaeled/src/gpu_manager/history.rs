@@ -0,0 +1,98 @@
+use std::{
+    collections::VecDeque,
+    sync::{MutexGuard, PoisonError},
+};
+
+use chrono::{DateTime, Local};
+use tjaele_types::{DeviceHistory, GpuHistory, HistoryField, HistorySample};
+use tracing::warn;
+
+use super::GpuManager;
+
+impl GpuManager {
+    /// Reads telemetry of the GPU and appends it to its history,
+    /// dropping the oldest samples beyond `history_samples`.
+    ///
+    /// The sample is also written to disk if logging is configured.
+    /// Telemetry is not needed for fan control, so a failed read only skips the sample.
+    pub fn record_history(&self, device_idx: usize) {
+        let (max_samples, logging) = {
+            let control_config = self.control_config();
+            (control_config.history_samples, control_config.logging.clone())
+        };
+        let runtime = match self
+            .backend
+            .read_runtime_params(device_idx, self.persistent_params[device_idx].num_fans)
+        {
+            Ok(runtime) => runtime,
+            Err(err) => {
+                warn!("Skipping history sample of GPU {device_idx}: {err:#}");
+                return;
+            },
+        };
+
+        let sample = HistorySample {
+            time: runtime.probe_time,
+            temperature: Some(runtime.device_temperature),
+            duties: Some(runtime.fan_states.iter().map(|fan| fan.duty).collect()),
+            speeds: Some(runtime.fan_states.iter().map(|fan| fan.speed).collect()),
+            power: Some(runtime.power_usage),
+            clocks: Some(runtime.clock_speeds.clone()),
+        };
+        self.control_states()[device_idx].last_runtime = runtime;
+
+        if let Some(logging) = &logging {
+            self.recorder
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record(device_idx, &sample, logging);
+        }
+
+        let mut history = self.history();
+        let samples = &mut history[device_idx];
+        while samples.len() >= max_samples {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    /// Samples taken after `since` (all kept if not provided),
+    /// limited to `fields` (all if empty)
+    pub fn read_history(
+        &self,
+        since: Option<DateTime<Local>>,
+        fields: &[HistoryField],
+    ) -> GpuHistory {
+        let wanted = |field| fields.is_empty() || fields.contains(&field);
+        let history = self.history();
+
+        let devices = history
+            .iter()
+            .enumerate()
+            .map(|(index, samples)| DeviceHistory {
+                index,
+                samples: samples
+                    .iter()
+                    .filter(|sample| since.is_none_or(|since| sample.time > since))
+                    .map(|sample| HistorySample {
+                        time: sample.time,
+                        temperature: sample
+                            .temperature
+                            .filter(|_| wanted(HistoryField::Temperature)),
+                        duties: sample.duties.clone().filter(|_| wanted(HistoryField::Duties)),
+                        speeds: sample.speeds.clone().filter(|_| wanted(HistoryField::Speeds)),
+                        power: sample.power.filter(|_| wanted(HistoryField::Power)),
+                        clocks: sample.clocks.clone().filter(|_| wanted(HistoryField::Clocks)),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        GpuHistory { devices }
+    }
+
+    fn history(&self) -> MutexGuard<'_, Vec<VecDeque<HistorySample>>> {
+        // samples are only pushed and popped whole
+        self.history.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+
+use super::control_config::FilterConfig;
+
+/// Smoothing state of one input, kept across config changes
+#[derive(Debug, Clone, Default)]
+pub(super) struct InputFilter {
+    /// Newest last, no longer than the window of the filter in use
+    samples: VecDeque<f64>,
+    ema: Option<f64>,
+    filtered: Option<f64>,
+}
+
+impl InputFilter {
+    /// Adds a raw sample and returns the filtered value
+    pub(super) fn update(&mut self, sample: f64, config: &FilterConfig) -> f64 {
+        let window = match config {
+            FilterConfig::MovingAverage { samples } | FilterConfig::WindowedMax { samples } => {
+                *samples
+            },
+            FilterConfig::None | FilterConfig::Ema { .. } => 1,
+        };
+
+        self.samples.push_back(sample);
+        while self.samples.len() > window {
+            self.samples.pop_front();
+        }
+
+        let filtered = match config {
+            FilterConfig::None => sample,
+            FilterConfig::MovingAverage { .. } => {
+                self.samples.iter().sum::<f64>() / self.samples.len() as f64
+            },
+            FilterConfig::Ema { alpha } => {
+                self.ema.map_or(sample, |ema| ema + alpha * (sample - ema))
+            },
+            FilterConfig::WindowedMax { .. } => {
+                self.samples.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+            },
+        };
+
+        // average restarts from the current sample when EMA is switched on
+        self.ema = matches!(config, FilterConfig::Ema { .. }).then_some(filtered);
+        self.filtered = Some(filtered);
+
+        filtered
+    }
+
+    pub(super) fn filtered(&self) -> Option<f64> {
+        self.filtered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InputFilter;
+    use crate::gpu_manager::control_config::FilterConfig;
+
+    const SAMPLES: [f64; 5] = [50.0, 60.0, 70.0, 40.0, 40.0];
+
+    fn filtered(config: &FilterConfig) -> Vec<f64> {
+        let mut filter = InputFilter::default();
+        SAMPLES.iter().map(|&sample| filter.update(sample, config)).collect()
+    }
+
+    #[test]
+    fn passes_samples_through_without_filter() {
+        assert_eq!(filtered(&FilterConfig::None), SAMPLES);
+    }
+
+    #[test]
+    fn averages_last_samples() {
+        let config = FilterConfig::MovingAverage { samples: 2 };
+
+        assert_eq!(filtered(&config), [50.0, 55.0, 65.0, 55.0, 40.0]);
+    }
+
+    #[test]
+    fn moves_by_alpha_of_difference() {
+        let config = FilterConfig::Ema { alpha: 0.5 };
+
+        assert_eq!(filtered(&config), [50.0, 55.0, 62.5, 51.25, 45.625]);
+    }
+
+    #[test]
+    fn keeps_highest_of_last_samples() {
+        let config = FilterConfig::WindowedMax { samples: 2 };
+
+        assert_eq!(filtered(&config), [50.0, 60.0, 70.0, 70.0, 40.0]);
+    }
+
+    #[test]
+    fn restarts_average_when_ema_is_switched_on() {
+        let mut filter = InputFilter::default();
+        filter.update(90.0, &FilterConfig::Ema { alpha: 0.5 });
+        filter.update(30.0, &FilterConfig::None);
+
+        assert_eq!(filter.update(50.0, &FilterConfig::Ema { alpha: 0.5 }), 50.0);
+        assert_eq!(filter.filtered(), Some(50.0));
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{LineWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use tjaele_types::HistorySample;
+use tracing::{info, warn};
+
+use super::control_config::LoggingConfig;
+
+const FILE_PREFIX: &str = "tjaele-";
+const FILE_EXTENSION: &str = "jsonl";
+
+/// Appends telemetry samples to JSONL files, one sample per line.
+///
+/// Files are named after the time they were started, so their names sort oldest first.
+#[derive(Debug, Default)]
+pub(super) struct TelemetryRecorder {
+    current: Option<RecordedFile>,
+    /// Only the first error in a row is logged
+    failing: bool,
+}
+
+#[derive(Debug)]
+struct RecordedFile {
+    path: PathBuf,
+    writer: LineWriter<File>,
+    opened: Instant,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct RecordedSample<'a> {
+    device: usize,
+    #[serde(flatten)]
+    sample: &'a HistorySample,
+}
+
+impl TelemetryRecorder {
+    /// Errors are logged instead of returned, so that a full disk cannot stop fan control
+    pub(super) fn record(&mut self, device: usize, sample: &HistorySample, config: &LoggingConfig) {
+        match self.try_record(device, sample, config, Instant::now(), Local::now()) {
+            Ok(()) if self.failing => {
+                info!("Telemetry recording resumed");
+                self.failing = false;
+            },
+            Ok(()) => {},
+            Err(err) => {
+                if !self.failing {
+                    warn!("Failed to record telemetry: {err:#}");
+                }
+                self.failing = true;
+                // next sample starts a new file
+                self.current = None;
+            },
+        }
+    }
+
+    /// `now` is used for the age of the file, `time` for the name of a new one
+    fn try_record(
+        &mut self,
+        device: usize,
+        sample: &HistorySample,
+        config: &LoggingConfig,
+        now: Instant,
+        time: DateTime<Local>,
+    ) -> Result<()> {
+        let mut line = serde_json::to_string(&RecordedSample { device, sample })?;
+        line.push('\n');
+        let line_size = line.len() as u64;
+
+        let rotate = self.current.as_ref().is_none_or(|file| {
+            file.path.parent() != Some(config.directory.as_path())
+                || file.size + line_size > config.max_file_size
+                || now.saturating_duration_since(file.opened) >= config.max_file_age
+        });
+        if rotate {
+            self.rotate(config, now, time)?;
+        }
+
+        let file = self.current.as_mut().context("No telemetry file open")?;
+        file.writer
+            .write_all(line.as_bytes())
+            .with_context(|| format!("Failed to write to {:?}", file.path))?;
+        file.size += line_size;
+
+        Ok(())
+    }
+
+    /// Starts a new file and removes the oldest ones above the total size limit
+    fn rotate(
+        &mut self,
+        config: &LoggingConfig,
+        now: Instant,
+        time: DateTime<Local>,
+    ) -> Result<()> {
+        self.current = None;
+        fs::create_dir_all(&config.directory)
+            .with_context(|| format!("Failed to create {:?}", config.directory))?;
+
+        let file_name = format!("{FILE_PREFIX}{}.{FILE_EXTENSION}", time.format("%Y%m%d-%H%M%S"));
+        let path = config.directory.join(file_name);
+        // file might exist if the service restarted within a second
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {path:?}"))?;
+        let size = file.metadata()?.len();
+
+        remove_old_files(config, &path)?;
+        info!("Recording telemetry to {path:?}");
+
+        self.current =
+            Some(RecordedFile { path, writer: LineWriter::new(file), opened: now, size });
+
+        Ok(())
+    }
+}
+
+fn remove_old_files(config: &LoggingConfig, current: &Path) -> Result<()> {
+    let mut files = fs::read_dir(&config.directory)?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let path = entry.path();
+            path.extension().is_some_and(|extension| extension == FILE_EXTENSION)
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(FILE_PREFIX))
+        })
+        .map(|entry| Ok((entry.path(), entry.metadata()?.len())))
+        .collect::<Result<Vec<_>>>()?;
+    files.retain(|(path, _)| path != current);
+    files.sort();
+
+    // current file has to fit as well, once it grows to the full size
+    let mut total_size = files.iter().map(|(_, size)| size).sum::<u64>();
+    for (path, size) in files {
+        if total_size + config.max_file_size <= config.max_total_size {
+            break;
+        }
+
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {path:?}"))?;
+        info!("Removed old telemetry file {path:?}");
+        total_size -= size;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        time::{Duration, Instant},
+    };
+
+    use chrono::{Local, TimeDelta, TimeZone};
+    use tjaele_types::HistorySample;
+
+    use super::{RecordedSample, TelemetryRecorder};
+    use crate::gpu_manager::control_config::LoggingConfig;
+
+    /// Directory in the temp directory, removed on drop
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("tjaeled-{}-recorder-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            TestDir(dir)
+        }
+
+        /// Names of recorded files, oldest first, with the number of samples in each
+        fn files(&self) -> Vec<(String, usize)> {
+            let mut files = fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| {
+                    let path = entry.unwrap().path();
+                    let lines = fs::read_to_string(&path).unwrap().lines().count();
+                    (path.file_name().unwrap().to_string_lossy().into_owned(), lines)
+                })
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn sample() -> HistorySample {
+        HistorySample {
+            time: Local.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap(),
+            temperature: Some(50),
+            duties: Some(vec![40, 40]),
+            speeds: Some(vec![38, 39]),
+            power: Some(120.5),
+            clocks: None,
+        }
+    }
+
+    /// Size of each recorded line
+    fn line_size() -> u64 {
+        serde_json::to_string(&RecordedSample { device: 0, sample: &sample() }).unwrap().len()
+            as u64
+            + 1
+    }
+
+    /// Records a sample at each of `seconds` after the start
+    fn record_at(config: &LoggingConfig, seconds: &[i64]) {
+        let mut recorder = TelemetryRecorder::default();
+        let start = Instant::now();
+        let start_time = sample().time;
+
+        for &second in seconds {
+            let now = start + Duration::from_secs(second.unsigned_abs());
+            let time = start_time + TimeDelta::seconds(second);
+            recorder.try_record(0, &sample(), config, now, time).unwrap();
+        }
+    }
+
+    #[test]
+    fn rotates_by_size_and_removes_oldest_files_above_total_size() {
+        let dir = TestDir::new("size");
+        // two samples per file, two old files next to the current one
+        let config = LoggingConfig {
+            directory: dir.0.clone(),
+            max_file_size: 2 * line_size(),
+            max_file_age: Duration::from_secs(3600),
+            max_total_size: 6 * line_size(),
+        };
+
+        record_at(&config, &[0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            dir.files(),
+            [
+                ("tjaele-20250101-120002.jsonl".to_string(), 2),
+                ("tjaele-20250101-120004.jsonl".to_string(), 2),
+                ("tjaele-20250101-120006.jsonl".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn rotates_by_age() {
+        let dir = TestDir::new("age");
+        let config = LoggingConfig {
+            directory: dir.0.clone(),
+            max_file_size: 1024 * 1024,
+            max_file_age: Duration::from_secs(60),
+            max_total_size: 16 * 1024 * 1024,
+        };
+
+        record_at(&config, &[0, 30, 59, 60, 90]);
+        assert_eq!(
+            dir.files(),
+            [
+                ("tjaele-20250101-120000.jsonl".to_string(), 3),
+                ("tjaele-20250101-120100.jsonl".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_files_left_by_previous_runs_in_total_size() {
+        let dir = TestDir::new("previous");
+        let config = LoggingConfig {
+            directory: dir.0.clone(),
+            max_file_size: 2 * line_size(),
+            max_file_age: Duration::from_secs(3600),
+            max_total_size: 4 * line_size(),
+        };
+
+        record_at(&config, &[0, 1]);
+        // other files in the directory are left alone
+        fs::write(dir.0.join("notes.txt"), "keep").unwrap();
+        record_at(&config, &[10, 11, 12]);
+        assert_eq!(
+            dir.files(),
+            [
+                ("notes.txt".to_string(), 1),
+                ("tjaele-20250101-120010.jsonl".to_string(), 2),
+                ("tjaele-20250101-120012.jsonl".to_string(), 1),
+            ]
+        );
+    }
+}
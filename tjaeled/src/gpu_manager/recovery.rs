@@ -0,0 +1,51 @@
+use anyhow::Result;
+use chrono::Local;
+use tjaele_types::{DaemonEvent, DegradedState};
+use tracing::info;
+
+use super::GpuManager;
+
+impl GpuManager {
+    /// Whether fan control can be tried again after the error
+    pub fn is_retryable(&self, err: &anyhow::Error) -> bool {
+        self.backend.is_retryable(err)
+    }
+
+    /// Marks the GPU as degraded and returns the number of consecutive failures
+    pub fn record_failure(&self, device_idx: usize, err: &anyhow::Error) -> u32 {
+        let mut control_states = self.control_states();
+        let control_state = &mut control_states[device_idx];
+
+        // nothing is known about the fans after a failure, so all duties are set again
+        control_state.target_duties.fill(None);
+        control_state.control_errors += 1;
+
+        let degraded = control_state.degraded.get_or_insert_with(|| DegradedState {
+            since: Local::now(),
+            failures: 0,
+            last_error: String::new(),
+        });
+        degraded.failures += 1;
+        degraded.last_error = format!("{err:#}");
+
+        degraded.failures
+    }
+
+    /// Initialises the backend again if the GPU still cannot be reached
+    pub fn recover(&self, device_idx: usize) -> Result<()> {
+        // fan controller of another GPU might have done it already
+        if self.backend.temperature(device_idx).is_ok() {
+            return Ok(());
+        }
+
+        self.backend.reinit()
+    }
+
+    /// Clears the degraded state after a successful control step
+    pub fn record_recovery(&self, device_idx: usize) {
+        if let Some(degraded) = self.control_states()[device_idx].degraded.take() {
+            info!("GPU {device_idx} fan control recovered after {} failures", degraded.failures);
+            self.publish(DaemonEvent::ControlRecovered { device: device_idx });
+        }
+    }
+}
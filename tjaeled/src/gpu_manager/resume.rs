@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+use tracing::info;
+
+use super::GpuManager;
+
+/// How much further the wall clock has to get than the monotonic clock
+/// between two control steps to consider it a system sleep
+const MIN_SLEEP: Duration = Duration::from_secs(5);
+
+impl GpuManager {
+    /// Takes fan control back if the system slept since the previous step,
+    /// as the driver may have reset fan policy meanwhile.
+    ///
+    /// Monotonic clock stops during sleep, while the wall clock keeps going.
+    pub fn check_resume(&self, device_idx: usize) -> Result<()> {
+        let now = (Instant::now(), SystemTime::now());
+        let previous = self.control_states()[device_idx].step_clocks.replace(now);
+
+        let slept = previous.and_then(|(monotonic, wall)| {
+            let wall_elapsed = now.1.duration_since(wall).ok()?;
+            wall_elapsed.checked_sub(now.0 - monotonic).filter(|slept| *slept >= MIN_SLEEP)
+        });
+
+        if let Some(slept) = slept {
+            info!(
+                "GPU {device_idx} resumed after system sleep of about {}s, taking fan control back",
+                slept.as_secs()
+            );
+            // handles might not survive the sleep
+            self.recover(device_idx)?;
+
+            let mut control_states = self.control_states();
+            let control_state = &mut control_states[device_idx];
+            control_state.target_duties.fill(None);
+            // readings from before the sleep say nothing about the GPU now
+            control_state.input_filters.clear();
+            drop(control_states);
+
+            self.bump_control_generation();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! Fan control over the simulated backend, without a GPU
+
+use std::{path::PathBuf, sync::Arc, thread, time::Duration};
+
+use tjaele_types::{DutyOverrideRequest, FanControlPolicy, FanCurveUpdate};
+
+use super::{GpuBackend, GpuManager, SimulatedBackend};
+
+/// Curve gives 50% at 50C and 60% at 55C
+const CONFIG: &str = r"
+response_time = 1.0
+hysteresis = 2
+fan_curve = [[40, 30], [50, 50], [60, 70]]
+
+[stall_detection]
+window = 1.0
+";
+
+/// Without fan cooling the GPU settles at 50C plus 1C per 10W within a simulation step,
+/// load is 0W, then 10W after 100s and 50W after 200s
+const SCENARIO: &str = r"
+ambient_temperature = 50.0
+
+[[device]]
+name = 'Test GPU'
+num_fans = 2
+heat_capacity = 1.0
+passive_conductance = 10.0
+fan_conductance = 0.0
+fan_time_constant = 0.1
+load = [[0, 0], [100, 10], [200, 50]]
+";
+
+/// Config and scenario written to the temp directory, removed on drop
+struct TestFiles {
+    config: PathBuf,
+    scenario: PathBuf,
+}
+
+impl TestFiles {
+    fn new(name: &str, scenario: &str) -> Self {
+        let dir = std::env::temp_dir();
+        let prefix = format!("tjaeled-{}-{name}", std::process::id());
+        let files = TestFiles {
+            config: dir.join(format!("{prefix}-config.toml")),
+            scenario: dir.join(format!("{prefix}-scenario.toml")),
+        };
+
+        std::fs::write(&files.config, CONFIG).unwrap();
+        std::fs::write(&files.scenario, scenario).unwrap();
+        files
+    }
+}
+
+impl Drop for TestFiles {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.config);
+        let _ = std::fs::remove_file(&self.scenario);
+    }
+}
+
+fn simulated_manager(files: &TestFiles) -> (GpuManager, Arc<SimulatedBackend>) {
+    let backend =
+        Arc::new(SimulatedBackend::new_from_file(&files.scenario).unwrap().with_manual_clock());
+    let gpu_manager = GpuManager::init(&files.config, Box::new(backend.clone())).unwrap();
+
+    (gpu_manager, backend)
+}
+
+fn fan_duties(backend: &SimulatedBackend) -> Vec<u32> {
+    (0..2).map(|fan_idx| backend.fan_duty(0, fan_idx).unwrap()).collect()
+}
+
+#[test]
+fn follows_fan_curve_with_hysteresis() {
+    let files = TestFiles::new("curve", SCENARIO);
+    let (gpu_manager, backend) = simulated_manager(&files);
+    let mut controller = gpu_manager.controller(0);
+
+    backend.advance(50.0);
+    assert_eq!(backend.temperature(0).unwrap(), 50);
+    gpu_manager.set_duty_with_controller(0, controller.as_mut()).unwrap();
+    assert_eq!(fan_duties(&backend), [50, 50]);
+
+    // 51C would be 52% on the curve, but the change is within hysteresis
+    backend.advance(100.0);
+    assert_eq!(backend.temperature(0).unwrap(), 51);
+    gpu_manager.set_duty_with_controller(0, controller.as_mut()).unwrap();
+    assert_eq!(fan_duties(&backend), [50, 50]);
+
+    backend.advance(100.0);
+    assert_eq!(backend.temperature(0).unwrap(), 55);
+    gpu_manager.set_duty_with_controller(0, controller.as_mut()).unwrap();
+    assert_eq!(fan_duties(&backend), [60, 60]);
+}
+
+#[test]
+fn returns_fans_to_automatic_on_drop() {
+    let files = TestFiles::new("drop", SCENARIO);
+    let (gpu_manager, backend) = simulated_manager(&files);
+    let mut controller = gpu_manager.controller(0);
+
+    gpu_manager.set_duty_with_controller(0, controller.as_mut()).unwrap();
+    for fan_idx in 0..2 {
+        let policy = backend.fan_control_policy(0, fan_idx).unwrap();
+        assert!(matches!(policy, FanControlPolicy::Manual), "fan {fan_idx} is {policy}");
+    }
+
+    drop(gpu_manager);
+    for fan_idx in 0..2 {
+        let policy = backend.fan_control_policy(0, fan_idx).unwrap();
+        assert!(matches!(policy, FanControlPolicy::Automatic), "fan {fan_idx} is {policy}");
+    }
+}
+
+#[test]
+fn keeps_fan_alarms_until_acknowledged() {
+    let files = TestFiles::new("stall", &format!("{SCENARIO}fan_failures = [[1, 0.0]]\n"));
+    let (gpu_manager, backend) = simulated_manager(&files);
+    let mut controller = gpu_manager.controller(0);
+    let alarms = || {
+        gpu_manager.control_states()[0]
+            .fan_stalls
+            .iter()
+            .filter_map(|fan_stall| fan_stall.alarm.as_ref().map(|alarm| alarm.fan))
+            .collect::<Vec<_>>()
+    };
+
+    gpu_manager.set_duty_with_controller(0, controller.as_mut()).unwrap();
+    backend.advance(10.0);
+    gpu_manager.check_fan_stalls(0).unwrap();
+    // stall window is measured by tjaeled itself, so only real time can pass it
+    thread::sleep(Duration::from_millis(1100));
+    gpu_manager.check_fan_stalls(0).unwrap();
+    assert_eq!(alarms(), [1]);
+
+    let update = FanCurveUpdate { device: None, fan_curve: vec![(40, 40), (50, 60), (60, 80)] };
+    gpu_manager.replace_fan_curve(update).unwrap();
+    gpu_manager.check_fan_stalls(0).unwrap();
+    assert_eq!(alarms(), [1]);
+
+    gpu_manager.clear_fan_alarms();
+    assert!(alarms().is_empty());
+}
+
+#[test]
+fn applies_duty_override_without_temperature_thresholds() {
+    let scenario =
+        format!("{SCENARIO}temp_thresholds = {{ shutdown = 0, slowdown = 0, gpumax = 0 }}\n");
+    let files = TestFiles::new("override", &scenario);
+    let (gpu_manager, backend) = simulated_manager(&files);
+
+    let request = DutyOverrideRequest { device: None, duty: 80, fans: None, duration: None };
+    gpu_manager.set_duty_override(request).unwrap();
+    backend.advance(50.0);
+    gpu_manager.apply_duty_override(0).unwrap();
+    assert_eq!(fan_duties(&backend), [80, 80]);
+}
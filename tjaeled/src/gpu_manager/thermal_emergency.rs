@@ -0,0 +1,101 @@
+use anyhow::Result;
+use chrono::Local;
+use tjaele_types::{DaemonEvent, EmergencyReaction, GpuTemperatureThresholds, SafetyEvent};
+use tracing::{error, info};
+
+use super::{control_config::ThermalEmergencyConfig, DeviceControlState, GpuManager};
+
+/// Number of safety events kept for each GPU
+const MAX_SAFETY_EVENTS: usize = 16;
+
+impl GpuManager {
+    /// Runs before every duty write, returns `true` while the emergency holds the fans
+    /// and nothing else may set them.
+    ///
+    /// Config has to be passed in, because this runs with control states locked.
+    pub(super) fn thermal_guard(
+        &self,
+        device_idx: usize,
+        control_state: &mut DeviceControlState,
+        config: &ThermalEmergencyConfig,
+    ) -> Result<bool> {
+        let Some(limit) = temperature_limit(&self.persistent_params[device_idx].temp_thresholds)
+        else {
+            return Ok(false);
+        };
+        let threshold = limit.saturating_sub(config.margin);
+
+        let temp = self.backend.temperature(device_idx)?;
+
+        let active_event =
+            control_state.safety_events.last_mut().filter(|event| event.ended.is_none());
+
+        let reaction = match active_event {
+            Some(event) => {
+                event.max_temperature = event.max_temperature.max(temp);
+
+                if temp + config.release <= event.threshold {
+                    event.ended = Some(Local::now());
+                    let event = event.clone();
+                    info!(
+                        "GPU {device_idx} thermal emergency ended at {temp}C (max {}C), \
+                         returning to normal control",
+                        event.max_temperature
+                    );
+                    // controller has to set its duty again right away
+                    self.bump_control_generation();
+                    self.publish(DaemonEvent::ThermalEmergency { device: device_idx, event });
+                    return Ok(false);
+                }
+
+                event.reaction
+            },
+            None if temp >= threshold => {
+                error!(
+                    "GPU {device_idx} thermal emergency - temperature {temp}C reached {threshold}C \
+                     ({}C below limit), reaction: {}",
+                    config.margin, config.reaction
+                );
+
+                if control_state.safety_events.len() >= MAX_SAFETY_EVENTS {
+                    control_state.safety_events.remove(0);
+                }
+                let event = SafetyEvent {
+                    started: Local::now(),
+                    ended: None,
+                    threshold,
+                    max_temperature: temp,
+                    reaction: config.reaction,
+                };
+                control_state.safety_events.push(event.clone());
+                self.publish(DaemonEvent::ThermalEmergency { device: device_idx, event });
+
+                config.reaction
+            },
+            None => return Ok(false),
+        };
+
+        // asserted on every step, in case anything else touched the fans
+        for fan_idx in 0..control_state.target_duties.len() {
+            match reaction {
+                EmergencyReaction::MaxDuty => {
+                    self.set_fan_duty(device_idx, control_state, fan_idx, 100)?;
+                    control_state.duty_ramp.hold(fan_idx, 100);
+                },
+                EmergencyReaction::Automatic => {
+                    self.backend.set_default_fan_policy(device_idx, fan_idx)?;
+                    // duty has to be set again when control is taken back
+                    control_state.target_duties[fan_idx] = None;
+                },
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Lower of slowdown and gpumax temperatures, none if the driver reports neither
+/// (unknown thresholds are reported as 0)
+pub(super) fn temperature_limit(thresholds: &GpuTemperatureThresholds) -> Option<u32> {
+    [thresholds.slowdown, thresholds.gpumax].into_iter().filter(|&limit| limit > 0).min()
+}
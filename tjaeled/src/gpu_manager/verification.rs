@@ -0,0 +1,66 @@
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use tjaele_types::FanControlPolicy;
+use tracing::warn;
+
+use super::GpuManager;
+
+impl GpuManager {
+    /// Reads policy and duty of fans controlled by tjaeled back once every
+    /// `verify_interval` and takes back fans which something else changed
+    /// (another tool or the driver itself).
+    ///
+    /// Fans taken back get their duty set again in the following control step.
+    pub fn verify_fans(&self, device_idx: usize) -> Result<()> {
+        let verify_interval = self.control_config().verify_interval;
+
+        let target_duties = {
+            let mut control_states = self.control_states();
+            let control_state = &mut control_states[device_idx];
+            if control_state.last_verified.is_some_and(|at| at.elapsed() < verify_interval) {
+                return Ok(());
+            }
+            control_state.last_verified = Some(Instant::now());
+            control_state.target_duties.clone()
+        };
+
+        let mut changed_fans = Vec::new();
+        for (fan_idx, target_duty) in target_duties.iter().enumerate() {
+            let Some(target_duty) = *target_duty else {
+                continue;
+            };
+
+            let policy = self
+                .backend
+                .fan_control_policy(device_idx, fan_idx)
+                .with_context(|| format!("Failed to read fan_{fan_idx} policy"))?;
+            let duty = self
+                .backend
+                .fan_duty(device_idx, fan_idx)
+                .with_context(|| format!("Failed to read fan_{fan_idx} duty"))?;
+
+            if !matches!(policy, FanControlPolicy::Automatic) && duty == u32::from(target_duty) {
+                continue;
+            }
+
+            warn!(
+                "GPU {device_idx} fan {fan_idx} was changed from outside (policy {policy}, duty \
+                 {duty}% instead of {target_duty}%), taking it back"
+            );
+            changed_fans.push(fan_idx);
+        }
+
+        let mut control_states = self.control_states();
+        let control_state = &mut control_states[device_idx];
+        for fan_idx in changed_fans {
+            control_state.interference_events += 1;
+            // unless tjaeled set another duty in the meantime
+            if control_state.target_duties[fan_idx] == target_duties[fan_idx] {
+                control_state.target_duties[fan_idx] = None;
+            }
+        }
+
+        Ok(())
+    }
+}
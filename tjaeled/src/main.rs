@@ -1,30 +1,52 @@
 mod gpu_manager;
+mod metrics;
 
+use std::convert::Infallible;
 use std::fmt::Debug;
 use std::fs;
+use std::net::Ipv4Addr;
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
+use std::time::Duration;
 
 use std::{path::PathBuf, sync::Arc};
 
-use anyhow::{anyhow, bail, Context, Result};
-use clap::{command, Parser};
-use gpu_manager::GpuManager;
-use http_body_util::Full;
-use hyper::body::{Bytes, Incoming};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use clap::Parser;
+use gpu_manager::{GpuBackend, GpuManager, NvmlHandle, SimulatedBackend, TjaeleControlConfig};
+use http_body_util::{combinators::UnsyncBoxBody, BodyExt, Full, Limited, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::{header::HeaderValue, Method, Request, Response, StatusCode};
 use hyper::{server::conn::http1, service::service_fn};
-use hyper::{Method, Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
-use tjaele_types::SOCKET;
+use serde::{de::IntoDeserializer, Deserialize, Serialize};
+use tjaele_types::api::{
+    paths, ApiError, ApiErrorKind, HistoryQuery, VersionInfo, API_VERSION, API_VERSION_HEADER,
+};
+use tjaele_types::{DaemonEvent, HistoryField, SOCKET};
 use tokio::signal::unix::SignalKind;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::{
-    net::{UnixListener, UnixStream},
+    net::{TcpListener, UnixListener, UnixStream},
     select, task,
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn, Level};
 use tracing_log::LogTracer;
 
+/// Body of responses on the Unix socket, which may be streamed
+type HttpBody = UnsyncBoxBody<Bytes, Infallible>;
+
+/// Config files are small, anything bigger is certainly a mistake
+const MAX_REQUEST_BODY_SIZE: usize = 64 * 1024;
+
+/// Consecutive retryable failures of fan control after which the service gives up
+const MAX_RETRIES: u32 = 8;
+/// Delay before the first retry, doubled with every consecutive failure
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
 #[derive(Parser)]
 #[command(
     version,
@@ -36,6 +58,15 @@ struct Cli {
     /// Path to the configuration file
     #[arg(short, long, required = true)]
     config_path: PathBuf,
+    /// Path to the Unix socket the service listens on
+    #[arg(short, long, default_value = SOCKET)]
+    socket_path: PathBuf,
+    /// Also serve `GET /metrics` over TCP on localhost at this port
+    #[arg(long, value_name = "PORT")]
+    metrics_port: Option<u16>,
+    /// Control GPUs simulated according to the scenario file instead of real devices
+    #[arg(long, value_name = "SCENARIO_PATH")]
+    simulate: Option<PathBuf>,
 }
 
 #[tokio::main(worker_threads = 4)]
@@ -58,25 +89,52 @@ async fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
-    let socket_listener = VolatileSocket::bind(SOCKET).context(
+    let socket_listener = VolatileSocket::bind(&cli.socket_path).context(
         "Failed to bind to socket, this is most likely because another tjaele instance is running or you are running without sudo",
     )?;
 
-    let gpu_manager = task::spawn_blocking(|| GpuManager::init(cli.config_path)).await??;
+    let metrics_listener = match cli.metrics_port {
+        Some(port) => Some(
+            TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .await
+                .with_context(|| format!("Failed to bind metrics listener to port {port}"))?,
+        ),
+        None => None,
+    };
+
+    let gpu_manager = task::spawn_blocking(move || -> Result<GpuManager> {
+        let backend: Box<dyn GpuBackend> = match cli.simulate {
+            Some(scenario_path) => Box::new(SimulatedBackend::new_from_file(scenario_path)?),
+            None => Box::new(NvmlHandle::init()?),
+        };
+        GpuManager::init(cli.config_path, backend)
+    })
+    .await??;
     let gpu_manager = Arc::new(gpu_manager);
-    info!("Successfully initialized connection with NVML");
+    info!("Successfully initialized connection with GPU backend");
 
     let server_token = CancellationToken::new();
     let child_token = server_token.child_token();
 
-    let gpu_manager_clone = gpu_manager.clone();
-    tokio::spawn(fan_control(gpu_manager_clone, server_token));
-
-    select! {
-        res = unix_socket_server(gpu_manager, socket_listener) => {return res}
-        _ = child_token.cancelled() => {error!("Server has been stopped by error in Fan Controller"); bail!("")}
-        r = capture_signals() => {return r}
+    for device_idx in 0..gpu_manager.device_count() {
+        tokio::spawn(fan_control(gpu_manager.clone(), device_idx, server_token.clone()));
     }
+
+    let result = select! {
+        res = reload_on_hangup(gpu_manager.clone()) => {res}
+        res = metrics_server(gpu_manager.clone(), metrics_listener) => {res}
+        res = unix_socket_server(gpu_manager.clone(), socket_listener) => {res}
+        _ = child_token.cancelled() => {error!("Server has been stopped by error in Fan Controller"); Err(anyhow!(""))}
+        r = capture_signals() => {r}
+    };
+
+    // failing to give fans back to the driver is reported by the exit status
+    let release_result = task::spawn_blocking(move || gpu_manager.release_fans())
+        .await
+        .map_err(|err| anyhow!("Join error: {err}"))
+        .and_then(std::convert::identity); //flatten the error
+
+    result.and(release_result)
 }
 
 #[tracing::instrument]
@@ -98,14 +156,62 @@ async fn unix_socket_server(
     }
 }
 
+/// Serves only the metrics, so that Prometheus does not need access to the socket
+#[tracing::instrument]
+async fn metrics_server(
+    gpu_manager: Arc<GpuManager>,
+    metrics_listener: Option<TcpListener>,
+) -> Result<()> {
+    let Some(metrics_listener) = metrics_listener else {
+        return std::future::pending().await;
+    };
+    info!("Serving metrics on {}", metrics_listener.local_addr()?);
+
+    loop {
+        match metrics_listener.accept().await {
+            Ok((stream, _addr)) => {
+                let gmanager = gpu_manager.clone();
+                task::spawn(async move {
+                    let service = service_fn(|req: Request<Incoming>| {
+                        let gmanager = gmanager.clone();
+                        async move {
+                            match (req.method(), req.uri().path()) {
+                                (&Method::GET, paths::METRICS) => get_metrics(gmanager).await,
+                                _ => error_response(
+                                    StatusCode::NOT_FOUND,
+                                    &anyhow!("Only {} is served here", paths::METRICS),
+                                ),
+                            }
+                        }
+                    });
+
+                    if let Err(err) =
+                        http1::Builder::new().serve_connection(TokioIo::new(stream), service).await
+                    {
+                        error!("Error serving metrics connection: {err}")
+                    }
+                });
+            },
+            Err(e) => {
+                error!("Metrics listener accept() returned error {e}")
+            },
+        }
+    }
+}
+
 #[tracing::instrument]
 async fn handle_socket_stream(io_stream: UnixStream, gpu_manager: Arc<GpuManager>) {
+    // everyone can read the state, but only root can modify fan control
+    let privileged = io_stream.peer_cred().is_ok_and(|cred| cred.uid() == 0);
     let io = TokioIo::new(io_stream);
     let gmanager = gpu_manager.clone();
 
     task::spawn(async move {
         if let Err(err) = http1::Builder::new()
-            .serve_connection(io, service_fn(|req| handle_http_request(req, gmanager.clone())))
+            .serve_connection(
+                io,
+                service_fn(|req| handle_http_request(req, gmanager.clone(), privileged)),
+            )
             .await
         {
             error!("Error serving connection: {err}")
@@ -117,55 +223,286 @@ async fn handle_socket_stream(io_stream: UnixStream, gpu_manager: Arc<GpuManager
 async fn handle_http_request(
     req: Request<Incoming>,
     gpu_manager: Arc<GpuManager>,
-) -> Result<Response<Full<Bytes>>, hyper::http::Error> {
-    if req.method() != Method::GET || req.uri().path() != "/gpustate" {
-        return Response::builder().status(StatusCode::NOT_FOUND).body(Full::new(Bytes::from("")));
-    }
+    privileged: bool,
+) -> Result<Response<HttpBody>, hyper::http::Error> {
+    let method = req.method().clone();
+
+    let response = match (&method, req.uri().path()) {
+        (&Method::GET, paths::EVENTS) => event_stream(&gpu_manager),
+        (&Method::GET, paths::VERSION) => json_response(&VersionInfo {
+            api_version: API_VERSION,
+            daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+        }),
+        (&Method::GET, paths::GPU_STATE) => get_gpu_state(gpu_manager).await,
+        (&Method::GET, paths::METRICS) => get_metrics(gpu_manager).await,
+        (&Method::GET, paths::HISTORY) => get_history(&gpu_manager, req.uri().query()),
+        (
+            &Method::PUT | &Method::DELETE,
+            paths::FAN_CURVE | paths::CONFIG | paths::OVERRIDE | paths::ALARMS,
+        ) if !privileged => {
+            error_response(StatusCode::FORBIDDEN, &anyhow!("Only root can modify fan control"))
+        },
+        (&Method::PUT, paths::FAN_CURVE) => {
+            let result = read_body(req).await.and_then(|body| {
+                let update = serde_json::from_slice(&body).context("Invalid fan curve request")?;
+                gpu_manager.replace_fan_curve(update)
+            });
+            empty_response(result)
+        },
+        (&Method::PUT, paths::CONFIG) => {
+            let result = read_body(req).await.and_then(|body| {
+                let config = String::from_utf8(body.to_vec()).context("Config is not UTF-8")?;
+                let config = TjaeleControlConfig::new_from_str(&config)?;
+                gpu_manager.replace_config(config)
+            });
+            empty_response(result)
+        },
+        (&Method::PUT, paths::OVERRIDE) => {
+            let result = read_body(req).await.and_then(|body| {
+                let request = serde_json::from_slice(&body).context("Invalid override request")?;
+                gpu_manager.set_duty_override(request)
+            });
+            empty_response(result)
+        },
+        (&Method::DELETE, paths::OVERRIDE) => {
+            gpu_manager.clear_duty_overrides();
+            empty_response(Ok(()))
+        },
+        (&Method::DELETE, paths::ALARMS) => {
+            gpu_manager.clear_fan_alarms();
+            empty_response(Ok(()))
+        },
+        _ => error_response(StatusCode::NOT_FOUND, &anyhow!("No such endpoint")),
+    };
+
+    // clients refuse to talk to a daemon they do not understand
+    response.map(|mut response| {
+        response.headers_mut().insert(API_VERSION_HEADER, HeaderValue::from(API_VERSION));
+        response
+    })
+}
 
+/// Server-sent events, each one named after the event with its JSON as data
+fn event_stream(gpu_manager: &GpuManager) -> Result<Response<HttpBody>, hyper::http::Error> {
+    let events = futures::stream::unfold(gpu_manager.subscribe(), |mut receiver| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Event stream client is too slow, {missed} events skipped");
+                    continue;
+                },
+                Err(RecvError::Closed) => return None,
+            };
+
+            match serde_json::to_string(&event) {
+                Ok(data) => {
+                    let frame =
+                        Frame::data(Bytes::from(format!("event: {event}\ndata: {data}\n\n")));
+                    return Some((Ok(frame), receiver));
+                },
+                Err(err) => error!("Failed to serialize {event} event: {err}"),
+            }
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+        .header(hyper::header::CACHE_CONTROL, "no-cache")
+        .body(StreamBody::new(events).boxed_unsync())
+}
+
+async fn get_gpu_state(
+    gpu_manager: Arc<GpuManager>,
+) -> Result<Response<HttpBody>, hyper::http::Error> {
     let gpu_state = task::spawn_blocking(move || gpu_manager.read_state())
         .await
         .map_err(|err| anyhow!("Join error: {err}"))
-        .and_then(std::convert::identity) //flatten the error
-        .and_then(|state| {
-            serde_json::to_string(&state).map_err(|err| anyhow!("Serialization failed: {err}"))
-        });
+        .and_then(std::convert::identity); //flatten the error
 
     match gpu_state {
-        Ok(state) => {
-            let body = Bytes::from(state);
-            let body = Full::new(body);
-            Response::builder().status(StatusCode::OK).body(body)
-        },
+        Ok(state) => json_response(&state),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &err),
+    }
+}
+
+async fn get_metrics(
+    gpu_manager: Arc<GpuManager>,
+) -> Result<Response<HttpBody>, hyper::http::Error> {
+    let metrics = task::spawn_blocking(move || gpu_manager.read_state())
+        .await
+        .map_err(|err| anyhow!("Join error: {err}"))
+        .and_then(std::convert::identity) //flatten the error
+        .map(|state| metrics::render(&state));
+
+    match metrics {
+        Ok(metrics) => Response::builder()
+            .status(StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, metrics::CONTENT_TYPE)
+            .body(full_body(metrics)),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &err),
+    }
+}
+
+fn get_history(
+    gpu_manager: &GpuManager,
+    query: Option<&str>,
+) -> Result<Response<HttpBody>, hyper::http::Error> {
+    match parse_history_query(query.unwrap_or_default()) {
+        Ok((since, fields)) => json_response(&gpu_manager.read_history(since, &fields)),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, &err),
+    }
+}
+
+fn parse_history_query(query: &str) -> Result<(Option<DateTime<Local>>, Vec<HistoryField>)> {
+    let query: HistoryQuery = serde_urlencoded::from_str(query).context("Invalid history query")?;
+
+    let fields = query
+        .fields
+        .iter()
+        .flat_map(|fields| fields.split(','))
+        .map(|field| {
+            HistoryField::deserialize(field.trim().into_deserializer())
+                .map_err(|err: serde::de::value::Error| anyhow!("Invalid history field: {err}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((query.since, fields))
+}
+
+async fn read_body(req: Request<Incoming>) -> Result<Bytes> {
+    let body = Limited::new(req.into_body(), MAX_REQUEST_BODY_SIZE)
+        .collect()
+        .await
+        .map_err(|err| anyhow!("Failed to read request body: {err}"))?;
+
+    Ok(body.to_bytes())
+}
+
+/// Responds to requests which only modify the daemon state
+fn empty_response(result: Result<()>) -> Result<Response<HttpBody>, hyper::http::Error> {
+    match result {
+        Ok(()) => Response::builder().status(StatusCode::OK).body(full_body("")),
         Err(err) => {
-            let mut error_text = "Error chain:\n".to_string();
-            for (i, e) in err.chain().enumerate() {
-                error_text.push_str(&format!("[{i}]: {e}\n"));
-            }
-            let body = Bytes::from(error_text);
-            let body = Full::new(body);
-            Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(body)
+            warn!("Rejected request: {err:#}");
+            error_response(StatusCode::BAD_REQUEST, &err)
         },
     }
 }
 
+/// Error as JSON [`ApiError`], its kind follows from the status
+fn error_response(
+    status: StatusCode,
+    err: &anyhow::Error,
+) -> Result<Response<HttpBody>, hyper::http::Error> {
+    let kind = match status {
+        StatusCode::BAD_REQUEST => ApiErrorKind::InvalidRequest,
+        StatusCode::FORBIDDEN => ApiErrorKind::Forbidden,
+        StatusCode::NOT_FOUND => ApiErrorKind::NotFound,
+        _ => ApiErrorKind::Internal,
+    };
+    let api_error = ApiError { kind, chain: err.chain().map(ToString::to_string).collect() };
+
+    let mut response = json_response(&api_error)?;
+    *response.status_mut() = status;
+    Ok(response)
+}
+
+fn json_response<T: Serialize>(body: &T) -> Result<Response<HttpBody>, hyper::http::Error> {
+    match serde_json::to_string(body) {
+        Ok(body) => Response::builder()
+            .status(StatusCode::OK)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(full_body(body)),
+        Err(err) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(full_body(format!("Serialization failed: {err}"))),
+    }
+}
+
+fn full_body(body: impl Into<Bytes>) -> HttpBody {
+    Full::new(body.into()).boxed_unsync()
+}
+
 #[tracing::instrument]
-async fn fan_control(gpu_manager: Arc<GpuManager>, server_token: CancellationToken) {
-    info!("Starting Fan Controller");
-    let mut gpu_temp = 0;
+async fn fan_control(
+    gpu_manager: Arc<GpuManager>,
+    device_idx: usize,
+    server_token: CancellationToken,
+) {
+    info!("Starting Fan Controller for GPU {device_idx}");
+    let mut controller = gpu_manager.controller(device_idx);
+    let mut control_generation = gpu_manager.control_generation();
+    let mut failures = 0;
 
     loop {
+        // config changed or override ended, so the controller starts over with current config
+        if gpu_manager.control_generation() != control_generation {
+            control_generation = gpu_manager.control_generation();
+            controller = gpu_manager.controller(device_idx);
+        }
+
         let gpu_manager_clone = gpu_manager.clone();
-        let fan_control_result =
-            task::spawn_blocking(move || gpu_manager_clone.set_duty_with_curve(gpu_temp))
-                .await
-                .map_err(|err| anyhow!("Join error: {err}"))
-                .and_then(std::convert::identity); //flatten the error
+        let fan_control_result = task::spawn_blocking(move || {
+            gpu_manager_clone.check_resume(device_idx)?;
+            gpu_manager_clone.verify_fans(device_idx)?;
+            gpu_manager_clone.apply_duty_override(device_idx)?;
+            gpu_manager_clone.set_duty_with_controller(device_idx, controller.as_mut())?;
+            gpu_manager_clone.check_fan_stalls(device_idx)?;
+            gpu_manager_clone.record_history(device_idx);
+            gpu_manager_clone.publish_state(device_idx);
+            Ok(controller)
+        })
+        .await
+        .map_err(|err| anyhow!("Join error: {err}"))
+        .and_then(std::convert::identity); //flatten the error
 
         match fan_control_result {
-            Ok(t) => gpu_temp = t,
+            Ok(c) => {
+                controller = c;
+                if failures > 0 {
+                    gpu_manager.record_recovery(device_idx);
+                    failures = 0;
+                }
+            },
             Err(e) => {
-                error!("Fan control failed with error: {e}. Shutting down.");
+                // the failed step took the controller with it
+                controller = gpu_manager.controller(device_idx);
+                failures = gpu_manager.record_failure(device_idx, &e);
+                let retrying = gpu_manager.is_retryable(&e) && failures <= MAX_RETRIES;
+                gpu_manager.publish(DaemonEvent::ControlError {
+                    device: device_idx,
+                    error: format!("{e:#}"),
+                    retrying,
+                });
+
+                if retrying {
+                    let backoff = RETRY_BACKOFF
+                        .saturating_mul(2_u32.saturating_pow(failures - 1))
+                        .min(MAX_RETRY_BACKOFF);
+                    warn!(
+                        "Fan control for GPU {device_idx} failed ({failures}/{MAX_RETRIES}) with \
+                         error: {e:#}. Retrying in {backoff:?}."
+                    );
+                    tokio::time::sleep(backoff).await;
+
+                    let gpu_manager_clone = gpu_manager.clone();
+                    let recovery_result =
+                        task::spawn_blocking(move || gpu_manager_clone.recover(device_idx))
+                            .await
+                            .map_err(|err| anyhow!("Join error: {err}"))
+                            .and_then(std::convert::identity); //flatten the error
+                    if let Err(err) = recovery_result {
+                        warn!("GPU {device_idx} could not be recovered yet: {err:#}");
+                    }
+
+                    continue;
+                }
+
+                error!("Fan control for GPU {device_idx} failed with error: {e:#}. Shutting down.");
                 server_token.cancel();
+                return;
             },
         }
 
@@ -181,13 +518,33 @@ async fn capture_signals() -> Result<()> {
     let sigint = tokio::spawn(capture_signal(SignalKind::interrupt()));
     let sigquit = tokio::spawn(capture_signal(SignalKind::quit()));
     let sigterm = tokio::spawn(capture_signal(SignalKind::terminate()));
-    let sighup = tokio::spawn(capture_signal(SignalKind::hangup()));
 
     select! {
         r = sigint => {let r = r?; return r}
         r = sigquit => {let r = r?; return r}
         r = sigterm => {let r = r?; return r}
-        r = sighup => {let r = r?; return r}
+    }
+}
+
+/// By daemon convention SIGHUP re-reads the configuration
+/// Invalid config is rejected and the previous one stays in use
+#[tracing::instrument]
+async fn reload_on_hangup(gpu_manager: Arc<GpuManager>) -> Result<()> {
+    let mut sighup = tokio::signal::unix::signal(SignalKind::hangup())?;
+
+    loop {
+        sighup.recv().await.context("No more signals can be received")?;
+        info!("Received SIGHUP, reloading config");
+
+        let gpu_manager = gpu_manager.clone();
+        let reload_result = task::spawn_blocking(move || gpu_manager.reload_config())
+            .await
+            .map_err(|err| anyhow!("Join error: {err}"))
+            .and_then(std::convert::identity); //flatten the error
+
+        if let Err(err) = reload_result {
+            error!("Config reload failed, keeping previous config: {err:#}");
+        }
     }
 }
 
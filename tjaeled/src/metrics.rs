@@ -0,0 +1,426 @@
+use std::fmt::Write;
+
+use tjaele_types::{ControlMode, DeviceState, FanState, GpuState};
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Renders the state in OpenMetrics text format, samples of each GPU are
+/// labelled with its index and UUID
+pub fn render(state: &GpuState) -> String {
+    let mut metrics = Metrics::default();
+    let devices = &state.devices;
+
+    metrics.gauge(
+        "tjaele_gpu_temperature_celsius",
+        "celsius",
+        "Core temperature",
+        devices
+            .iter()
+            .map(|device| (labels(device, &[]), f64::from(device.runtime.device_temperature))),
+    );
+    metrics.gauge(
+        "tjaele_gpu_memory_temperature_celsius",
+        "celsius",
+        "Memory temperature",
+        devices.iter().filter_map(|device| {
+            let temperature = device.runtime.memory_temperature?;
+            Some((labels(device, &[]), f64::from(temperature)))
+        }),
+    );
+    metrics.gauge(
+        "tjaele_fan_speed_percent",
+        "percent",
+        "Actual fan speed",
+        fan_samples(devices, |fan| Some(f64::from(fan.speed))),
+    );
+    metrics.gauge(
+        "tjaele_fan_duty_percent",
+        "percent",
+        "Duty the fan is set to",
+        fan_samples(devices, |fan| Some(f64::from(fan.duty))),
+    );
+    metrics.gauge(
+        "tjaele_fan_target_duty_percent",
+        "percent",
+        "Duty set by tjaeled, missing when the driver controls the fan",
+        fan_samples(devices, |fan| fan.target_duty.map(f64::from)),
+    );
+    metrics.gauge(
+        "tjaele_gpu_power_watts",
+        "watts",
+        "Power usage",
+        devices.iter().map(|device| (labels(device, &[]), device.runtime.power_usage)),
+    );
+    metrics.gauge(
+        "tjaele_gpu_power_limit_watts",
+        "watts",
+        "Enforced power limit",
+        devices
+            .iter()
+            .filter_map(|device| Some((labels(device, &[]), device.runtime.power_limit?))),
+    );
+    metrics.gauge(
+        "tjaele_gpu_clock_hertz",
+        "hertz",
+        "Clock speeds",
+        devices.iter().flat_map(|device| {
+            let clocks = &device.runtime.clock_speeds;
+            [
+                ("graphics", clocks.graphics),
+                ("memory", clocks.memory),
+                ("video", clocks.video),
+                ("streaming_multiprocessor", clocks.streaming_multiprocessor),
+            ]
+            .map(|(clock, mhz)| (labels(device, &[("clock", clock)]), f64::from(mhz) * 1e6))
+        }),
+    );
+    metrics.gauge(
+        "tjaele_gpu_memory_used_bytes",
+        "bytes",
+        "Used GPU memory",
+        devices.iter().map(|device| (labels(device, &[]), device.runtime.memory_info.used as f64)),
+    );
+    metrics.gauge(
+        "tjaele_gpu_memory_total_bytes",
+        "bytes",
+        "Total GPU memory",
+        devices.iter().map(|device| (labels(device, &[]), device.runtime.memory_info.total as f64)),
+    );
+    metrics.gauge(
+        "tjaele_gpu_pcie_link_generation",
+        "",
+        "Current PCIe link generation",
+        devices
+            .iter()
+            .map(|device| (labels(device, &[]), f64::from(device.runtime.current_pcie_link.gen))),
+    );
+    metrics.gauge(
+        "tjaele_gpu_pcie_link_width",
+        "",
+        "Current PCIe link width (lanes)",
+        devices
+            .iter()
+            .map(|device| (labels(device, &[]), f64::from(device.runtime.current_pcie_link.width))),
+    );
+    metrics.stateset(
+        "tjaele_control_mode",
+        "Strategy computing fan duty",
+        devices.iter().flat_map(|device| {
+            [ControlMode::Curve, ControlMode::Pid].map(|mode| {
+                let state = mode.to_string().to_lowercase();
+                let active = device.control_mode == mode;
+                (labels(device, &[("tjaele_control_mode", &state)]), f64::from(u8::from(active)))
+            })
+        }),
+    );
+    metrics.gauge(
+        "tjaele_thermal_emergency",
+        "",
+        "Whether fans are held by a thermal emergency",
+        devices.iter().map(|device| {
+            let active = device.safety_events.last().is_some_and(|event| event.ended.is_none());
+            (labels(device, &[]), f64::from(u8::from(active)))
+        }),
+    );
+    metrics.gauge(
+        "tjaele_fan_stalled",
+        "",
+        "Whether a stall alarm is raised for the fan",
+        devices.iter().flat_map(|device| {
+            (0..device.persistent.num_fans).map(|fan_idx| {
+                let stalled = device.fan_alarms.iter().any(|alarm| alarm.fan == fan_idx);
+                (labels(device, &[("fan", &fan_idx.to_string())]), f64::from(u8::from(stalled)))
+            })
+        }),
+    );
+    metrics.gauge(
+        "tjaele_degraded",
+        "",
+        "Whether fan control of the GPU is failing and being retried",
+        devices
+            .iter()
+            .map(|device| (labels(device, &[]), f64::from(u8::from(device.degraded.is_some())))),
+    );
+    metrics.counter(
+        "tjaele_duty_changes",
+        "Duty writes which changed the duty of a fan",
+        devices.iter().map(|device| (labels(device, &[]), device.duty_changes as f64)),
+    );
+    metrics.counter(
+        "tjaele_control_errors",
+        "Failed control steps",
+        devices.iter().map(|device| (labels(device, &[]), device.control_errors as f64)),
+    );
+    metrics.counter(
+        "tjaele_interference_events",
+        "Fans found changed by something other than tjaeled",
+        devices.iter().map(|device| (labels(device, &[]), f64::from(device.interference_events))),
+    );
+
+    metrics.finish()
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    text: String,
+}
+
+impl Metrics {
+    fn gauge(
+        &mut self,
+        name: &str,
+        unit: &str,
+        help: &str,
+        samples: impl IntoIterator<Item = (String, f64)>,
+    ) {
+        self.family(name, "gauge", unit, help, "", samples);
+    }
+
+    fn counter(
+        &mut self,
+        name: &str,
+        help: &str,
+        samples: impl IntoIterator<Item = (String, f64)>,
+    ) {
+        self.family(name, "counter", "", help, "_total", samples);
+    }
+
+    fn stateset(
+        &mut self,
+        name: &str,
+        help: &str,
+        samples: impl IntoIterator<Item = (String, f64)>,
+    ) {
+        self.family(name, "stateset", "", help, "", samples);
+    }
+
+    fn family(
+        &mut self,
+        name: &str,
+        kind: &str,
+        unit: &str,
+        help: &str,
+        suffix: &str,
+        samples: impl IntoIterator<Item = (String, f64)>,
+    ) {
+        // writing to String cannot fail
+        let _ = writeln!(self.text, "# TYPE {name} {kind}");
+        if !unit.is_empty() {
+            let _ = writeln!(self.text, "# UNIT {name} {unit}");
+        }
+        let _ = writeln!(self.text, "# HELP {name} {help}.");
+
+        for (labels, value) in samples {
+            let _ = writeln!(self.text, "{name}{suffix}{{{labels}}} {value}");
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.text.push_str("# EOF\n");
+        self.text
+    }
+}
+
+fn fan_samples<'a>(
+    devices: &'a [DeviceState],
+    value: impl Fn(&FanState) -> Option<f64> + Copy + 'a,
+) -> impl Iterator<Item = (String, f64)> + 'a {
+    devices.iter().flat_map(move |device| {
+        device.runtime.fan_states.iter().filter_map(move |fan| {
+            let value = value(fan)?;
+            Some((labels(device, &[("fan", &fan.index.to_string())]), value))
+        })
+    })
+}
+
+fn labels(device: &DeviceState, extra: &[(&str, &str)]) -> String {
+    let index = device.persistent.index.to_string();
+    [("gpu", index.as_str()), ("uuid", device.persistent.uuid.as_str())]
+        .iter()
+        .chain(extra)
+        .map(|(name, value)| format!("{name}=\"{}\"", escape(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+    use tjaele_types::{
+        ClockSpeeds, ControlMode, CudaComputeCapability, CudaVersion, DeviceState, FanAlarm,
+        FanControlPolicy, FanState, GpuArchitecture, GpuMemStats, GpuState,
+        GpuTemperatureThresholds, MinMaxFanSpeeds, PCIeLink, PersistentGpuParams, RuntimeGpuParams,
+        SafetyEvent, SysInfo,
+    };
+
+    use super::render;
+
+    const EXPECTED: &str = r#"# TYPE tjaele_gpu_temperature_celsius gauge
+# UNIT tjaele_gpu_temperature_celsius celsius
+# HELP tjaele_gpu_temperature_celsius Core temperature.
+tjaele_gpu_temperature_celsius{gpu="0",uuid="GPU-\"a\\b\"\n"} 65
+# TYPE tjaele_gpu_memory_temperature_celsius gauge
+# UNIT tjaele_gpu_memory_temperature_celsius celsius
+# HELP tjaele_gpu_memory_temperature_celsius Memory temperature.
+# TYPE tjaele_fan_speed_percent gauge
+# UNIT tjaele_fan_speed_percent percent
+# HELP tjaele_fan_speed_percent Actual fan speed.
+tjaele_fan_speed_percent{gpu="0",uuid="GPU-\"a\\b\"\n",fan="0"} 58
+tjaele_fan_speed_percent{gpu="0",uuid="GPU-\"a\\b\"\n",fan="1"} 28
+# TYPE tjaele_fan_duty_percent gauge
+# UNIT tjaele_fan_duty_percent percent
+# HELP tjaele_fan_duty_percent Duty the fan is set to.
+tjaele_fan_duty_percent{gpu="0",uuid="GPU-\"a\\b\"\n",fan="0"} 60
+tjaele_fan_duty_percent{gpu="0",uuid="GPU-\"a\\b\"\n",fan="1"} 30
+# TYPE tjaele_fan_target_duty_percent gauge
+# UNIT tjaele_fan_target_duty_percent percent
+# HELP tjaele_fan_target_duty_percent Duty set by tjaeled, missing when the driver controls the fan.
+tjaele_fan_target_duty_percent{gpu="0",uuid="GPU-\"a\\b\"\n",fan="0"} 60
+# TYPE tjaele_gpu_power_watts gauge
+# UNIT tjaele_gpu_power_watts watts
+# HELP tjaele_gpu_power_watts Power usage.
+tjaele_gpu_power_watts{gpu="0",uuid="GPU-\"a\\b\"\n"} 120.5
+# TYPE tjaele_gpu_power_limit_watts gauge
+# UNIT tjaele_gpu_power_limit_watts watts
+# HELP tjaele_gpu_power_limit_watts Enforced power limit.
+tjaele_gpu_power_limit_watts{gpu="0",uuid="GPU-\"a\\b\"\n"} 250
+# TYPE tjaele_gpu_clock_hertz gauge
+# UNIT tjaele_gpu_clock_hertz hertz
+# HELP tjaele_gpu_clock_hertz Clock speeds.
+tjaele_gpu_clock_hertz{gpu="0",uuid="GPU-\"a\\b\"\n",clock="graphics"} 1500000000
+tjaele_gpu_clock_hertz{gpu="0",uuid="GPU-\"a\\b\"\n",clock="memory"} 9501000000
+tjaele_gpu_clock_hertz{gpu="0",uuid="GPU-\"a\\b\"\n",clock="video"} 1200000000
+tjaele_gpu_clock_hertz{gpu="0",uuid="GPU-\"a\\b\"\n",clock="streaming_multiprocessor"} 1500000000
+# TYPE tjaele_gpu_memory_used_bytes gauge
+# UNIT tjaele_gpu_memory_used_bytes bytes
+# HELP tjaele_gpu_memory_used_bytes Used GPU memory.
+tjaele_gpu_memory_used_bytes{gpu="0",uuid="GPU-\"a\\b\"\n"} 3072
+# TYPE tjaele_gpu_memory_total_bytes gauge
+# UNIT tjaele_gpu_memory_total_bytes bytes
+# HELP tjaele_gpu_memory_total_bytes Total GPU memory.
+tjaele_gpu_memory_total_bytes{gpu="0",uuid="GPU-\"a\\b\"\n"} 4096
+# TYPE tjaele_gpu_pcie_link_generation gauge
+# HELP tjaele_gpu_pcie_link_generation Current PCIe link generation.
+tjaele_gpu_pcie_link_generation{gpu="0",uuid="GPU-\"a\\b\"\n"} 4
+# TYPE tjaele_gpu_pcie_link_width gauge
+# HELP tjaele_gpu_pcie_link_width Current PCIe link width (lanes).
+tjaele_gpu_pcie_link_width{gpu="0",uuid="GPU-\"a\\b\"\n"} 16
+# TYPE tjaele_control_mode stateset
+# HELP tjaele_control_mode Strategy computing fan duty.
+tjaele_control_mode{gpu="0",uuid="GPU-\"a\\b\"\n",tjaele_control_mode="curve"} 0
+tjaele_control_mode{gpu="0",uuid="GPU-\"a\\b\"\n",tjaele_control_mode="pid"} 1
+# TYPE tjaele_thermal_emergency gauge
+# HELP tjaele_thermal_emergency Whether fans are held by a thermal emergency.
+tjaele_thermal_emergency{gpu="0",uuid="GPU-\"a\\b\"\n"} 1
+# TYPE tjaele_fan_stalled gauge
+# HELP tjaele_fan_stalled Whether a stall alarm is raised for the fan.
+tjaele_fan_stalled{gpu="0",uuid="GPU-\"a\\b\"\n",fan="0"} 0
+tjaele_fan_stalled{gpu="0",uuid="GPU-\"a\\b\"\n",fan="1"} 1
+# TYPE tjaele_degraded gauge
+# HELP tjaele_degraded Whether fan control of the GPU is failing and being retried.
+tjaele_degraded{gpu="0",uuid="GPU-\"a\\b\"\n"} 0
+# TYPE tjaele_duty_changes counter
+# HELP tjaele_duty_changes Duty writes which changed the duty of a fan.
+tjaele_duty_changes_total{gpu="0",uuid="GPU-\"a\\b\"\n"} 42
+# TYPE tjaele_control_errors counter
+# HELP tjaele_control_errors Failed control steps.
+tjaele_control_errors_total{gpu="0",uuid="GPU-\"a\\b\"\n"} 0
+# TYPE tjaele_interference_events counter
+# HELP tjaele_interference_events Fans found changed by something other than tjaeled.
+tjaele_interference_events_total{gpu="0",uuid="GPU-\"a\\b\"\n"} 1
+# EOF
+"#;
+
+    fn fan(index: usize, duty: u32, target_duty: Option<u8>) -> FanState {
+        FanState {
+            index,
+            speed: duty - 2,
+            duty,
+            target_duty,
+            control_policy: FanControlPolicy::Manual,
+        }
+    }
+
+    /// GPU with two fans, one of them stalled and given back to the driver,
+    /// held by a thermal emergency
+    fn state() -> GpuState {
+        let time = Local.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+        let pcie_link = PCIeLink { gen: 4, width: 16, speed: 16000 };
+
+        let device = DeviceState {
+            runtime: RuntimeGpuParams {
+                probe_time: time,
+                current_pcie_link: pcie_link.clone(),
+                memory_info: GpuMemStats { free: 1024, total: 4096, used: 3072 },
+                power_usage: 120.5,
+                power_limit: Some(250.0),
+                device_temperature: 65,
+                filtered_temperature: Some(64.5),
+                memory_temperature: None,
+                fan_states: vec![fan(0, 60, Some(60)), fan(1, 30, None)],
+                clock_speeds: ClockSpeeds {
+                    memory: 9501,
+                    graphics: 1500,
+                    video: 1200,
+                    streaming_multiprocessor: 1500,
+                },
+            },
+            persistent: PersistentGpuParams {
+                sys_info: SysInfo {
+                    cuda_version: CudaVersion { major: 12, minor: 4 },
+                    driver_version: "550.54".to_string(),
+                    cuda_capability: CudaComputeCapability { major: 8, minor: 6 },
+                    nvml_version: "12.550".to_string(),
+                },
+                index: 0,
+                // not a real UUID, but exercises label escaping
+                uuid: "GPU-\"a\\b\"\n".to_string(),
+                pci_bus_id: "00000000:01:00.0".to_string(),
+                device_name: "Simulated GPU".to_string(),
+                architecture: GpuArchitecture::Ampere,
+                num_cores: 8704,
+                num_fans: 2,
+                max_pcie_link: pcie_link,
+                temp_thresholds: GpuTemperatureThresholds {
+                    shutdown: 98,
+                    slowdown: 95,
+                    gpumax: 93,
+                },
+                minmax_fan_speeds: MinMaxFanSpeeds { min: 30, max: 100 },
+            },
+            fan_curve: vec![(30, 30), (90, 100)],
+            fan_curve_anchors: vec![(30, 30), (90, 100)],
+            control_mode: ControlMode::Pid,
+            duty_override: None,
+            fan_alarms: vec![FanAlarm {
+                fan: 1,
+                raised: time,
+                duty: 60,
+                speed: 0,
+                reaction: Default::default(),
+            }],
+            safety_events: vec![SafetyEvent {
+                started: time,
+                ended: None,
+                threshold: 90,
+                max_temperature: 91,
+                reaction: Default::default(),
+            }],
+            degraded: None,
+            interference_events: 1,
+            duty_changes: 42,
+            control_errors: 0,
+        };
+
+        GpuState { devices: vec![device] }
+    }
+
+    #[test]
+    fn renders_openmetrics_text() {
+        assert_eq!(render(&state()), EXPECTED);
+    }
+}